@@ -5,8 +5,11 @@ use std::fs::{write, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 use std::{env, fs};
 
+use chrono::NaiveTime;
+
 use clap::{Parser, ValueEnum};
 use clap_complete::Shell;
 use color_eyre::eyre::Context;
@@ -16,7 +19,7 @@ use merge::Merge;
 use regex::Regex;
 use regex_split::RegexSplit;
 use serde::Deserialize;
-use strum::{EnumIter, EnumString, IntoEnumIterator, VariantNames};
+use strum::{AsRefStr, EnumIter, EnumString, IntoEnumIterator, VariantNames};
 use which_crate::which;
 
 use super::utils::editor;
@@ -74,6 +77,7 @@ pub enum Step {
     CustomCommands,
     DebGet,
     Deno,
+    Dism,
     Distrobox,
     DkpPacman,
     Dotnet,
@@ -107,10 +111,13 @@ pub enum Step {
     Mas,
     Maza,
     Micro,
+    MicrosoftStore,
+    Mirrors,
     Mise,
     Myrepos,
     Nix,
     Node,
+    Office,
     Opam,
     Pacdef,
     Pacstall,
@@ -157,7 +164,9 @@ pub enum Step {
     Waydroid,
     Winget,
     Wsl,
+    WslPackages,
     WslUpdate,
+    Xcode,
     Xcodes,
     Yadm,
     Yarn,
@@ -209,6 +218,43 @@ pub struct Windows {
     open_remotes_in_new_terminal: Option<bool>,
     wsl_update_pre_release: Option<bool>,
     wsl_update_use_web_download: Option<bool>,
+    windows_update_exclude_drivers: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    windows_update_excluded_kbs: Option<Vec<String>>,
+
+    windows_updates_auto_reboot: Option<UpdatesAutoReboot>,
+
+    abort_on_pending_reboot: Option<bool>,
+
+    microsoft_store_fallback_winget: Option<bool>,
+
+    office_display_level: Option<bool>,
+    office_force_app_shutdown: Option<bool>,
+
+    windows_resetbase: Option<bool>,
+
+    windows_sudo: Option<WindowsSudo>,
+
+    wsl_run_topgrade: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    wsl_distributions: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    choco_arguments: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    winget_arguments: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    scoop_arguments: Option<String>,
+
+    winget_include_unknown: Option<bool>,
+    winget_use_pinned: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    winget_sources: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -228,6 +274,8 @@ pub struct Distrobox {
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     containers: Option<Vec<String>>,
+
+    parallel: Option<usize>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -244,11 +292,45 @@ pub struct NPM {
     use_sudo: Option<bool>,
 }
 
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct MacOS {
+    macos_recommended_only: Option<bool>,
+    macos_install_os_upgrades: Option<bool>,
+    macos_restart: Option<MacOSRestart>,
+    xcodes_install_latest: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    mas_excluded_apps: Option<Vec<u64>>,
+}
+
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct FreeBSD {
+    freebsd_update_jails: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct NetBSD {
+    netbsd_build_from_source: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Illumos {
+    pkg_arguments: Option<String>,
+}
+
 #[derive(Deserialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Firmware {
     upgrade: Option<bool>,
+    rpi_eeprom_apply: Option<bool>,
+    #[serde(alias = "fwupd_exclude_devices")]
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    exclude_devices: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -256,6 +338,12 @@ pub struct Firmware {
 #[allow(clippy::upper_case_acronyms)]
 pub struct Flatpak {
     use_sudo: Option<bool>,
+    user_only: Option<bool>,
+    system_only: Option<bool>,
+    repair: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    installations: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -263,12 +351,23 @@ pub struct Flatpak {
 pub struct Brew {
     greedy_cask: Option<bool>,
     greedy_latest: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    greedy_casks: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    prefixes: Option<Vec<String>>,
+
+    cask_updater: Option<BrewCaskUpdater>,
+
     autoremove: Option<bool>,
     fetch_head: Option<bool>,
+    restart_services: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, AsRefStr)]
 #[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum ArchPackageManager {
     Autodetect,
     Aura,
@@ -281,6 +380,187 @@ pub enum ArchPackageManager {
     Yay,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NixosRebuildAction {
+    Switch,
+    Boot,
+    Test,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SuseMode {
+    Update,
+    DistUpgrade,
+    Patch,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransactionalUpdateReboot {
+    No,
+    Ask,
+    Yes,
+}
+
+/// Whether PSWindowsUpdate reboots after installing updates that require it.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum UpdatesAutoReboot {
+    Yes,
+    No,
+    Ask,
+    /// Schedule the reboot at the next occurrence of this time.
+    At(NaiveTime),
+}
+
+impl FromStr for UpdatesAutoReboot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yes" => Ok(UpdatesAutoReboot::Yes),
+            "no" => Ok(UpdatesAutoReboot::No),
+            "ask" => Ok(UpdatesAutoReboot::Ask),
+            _ => s
+                .strip_prefix("at=")
+                .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+                .map(UpdatesAutoReboot::At)
+                .ok_or_else(|| format!("invalid reboot mode {s:?}, expected \"yes\", \"no\", \"ask\" or \"at=HH:MM\"")),
+        }
+    }
+}
+
+impl TryFrom<String> for UpdatesAutoReboot {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A remote host to run Topgrade in, written either as its name or as a table with the
+/// arguments and environment of its Topgrade run.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "RemoteTopgradeEntry")]
+pub struct RemoteTopgrade {
+    pub host: String,
+    /// Extra arguments passed to the remote Topgrade
+    pub args: Vec<String>,
+    /// Environment variables set for the remote Topgrade
+    pub env: BTreeMap<String, String>,
+    /// Extra arguments passed to SSH for this host, after `ssh_arguments`
+    pub ssh_args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RemoteTopgradeEntry {
+    Host(String),
+    Table(RemoteTopgradeTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RemoteTopgradeTable {
+    host: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    ssh_args: Vec<String>,
+}
+
+impl From<RemoteTopgradeEntry> for RemoteTopgrade {
+    fn from(entry: RemoteTopgradeEntry) -> Self {
+        match entry {
+            RemoteTopgradeEntry::Host(host) => RemoteTopgrade {
+                host,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                ssh_args: Vec::new(),
+            },
+            RemoteTopgradeEntry::Table(table) => RemoteTopgrade {
+                host: table.host,
+                args: table.args,
+                env: table.env,
+                ssh_args: table.ssh_args,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BrewCaskUpdater {
+    Auto,
+    Brew,
+    Cu,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacOSRestart {
+    Never,
+    Ask,
+    Auto,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowsSudo {
+    Gsudo,
+    Sudo,
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PsResourceGet {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum FallbackPackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Apk,
+    Zypper,
+    Xbps,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreUpgradeSnapshot {
+    None,
+    Snapper,
+    Timeshift,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NeedrestartMode {
+    Interactive,
+    Auto,
+    List,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AptUpgradeMode {
+    FullUpgrade,
+    DistUpgrade,
+    Upgrade,
+}
+
 #[derive(Deserialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Linux {
@@ -294,6 +574,13 @@ pub struct Linux {
     aura_pacman_arguments: Option<String>,
     arch_package_manager: Option<ArchPackageManager>,
     show_arch_news: Option<bool>,
+    arch_news_check: Option<bool>,
+    paccache_keep: Option<u32>,
+    pacman_files_db: Option<bool>,
+    refresh_mirrors: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    reflector_arguments: Option<String>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     garuda_update_arguments: Option<String>,
@@ -315,23 +602,84 @@ pub struct Linux {
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     nix_env_arguments: Option<String>,
+    nix_gc_age: Option<String>,
+    snap_retain: Option<usize>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    toolbx_containers: Option<Vec<String>>,
+
+    waydroid_restart_session: Option<bool>,
+
+    pre_upgrade_snapshot: Option<PreUpgradeSnapshot>,
+    snapshot_failure_is_warning: Option<bool>,
+
+    check_failed_units: Option<bool>,
+
+    fallback_package_manager: Option<FallbackPackageManager>,
+
+    neon_offline_updates: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    am_apps: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    pacstall_args: Option<String>,
+    needrestart_mode: Option<NeedrestartMode>,
+
+    nixos_flake_path: Option<String>,
+    nixos_rebuild_action: Option<NixosRebuildAction>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     apt_arguments: Option<String>,
 
+    apt_dist_upgrade_mode: Option<AptUpgradeMode>,
+    mx_apt_command: Option<AptUpgradeMode>,
+    apt_max_removals: Option<usize>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    apk_arguments: Option<String>,
+
+    pop_upgrade: Option<bool>,
+    pop_upgrade_recovery: Option<bool>,
+
     enable_tlmgr: Option<bool>,
+    clearlinux_repair: Option<bool>,
     redhat_distro_sync: Option<bool>,
+    dnf_offline: Option<bool>,
+    dnf_offline_reboot: Option<bool>,
+    dnf_needs_restarting: Option<bool>,
+    amazon_linux_release_upgrade: Option<bool>,
     suse_dup: Option<bool>,
+    suse_mode: Option<SuseMode>,
+    transactional_update_reboot: Option<TransactionalUpdateReboot>,
     rpm_ostree: Option<bool>,
+    rpm_ostree_apply_live: Option<bool>,
+    bootc: Option<bool>,
+    ublue_update: Option<bool>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     emerge_sync_flags: Option<String>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     emerge_update_flags: Option<String>,
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    cave_resolve_arguments: Option<String>,
+    cave_purge: Option<bool>,
+    cave_fix_linkage: Option<bool>,
+    eclectic_config: Option<bool>,
+
+    gentoo_live_rebuild: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
+    gentoo_live_rebuild_args: Option<String>,
+    emerge_depclean: Option<bool>,
+    emerge_preserved_rebuild: Option<bool>,
+    glsa_check: Option<bool>,
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     home_manager_arguments: Option<Vec<String>>,
+
+    guix_system_config: Option<String>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -360,13 +708,21 @@ pub struct Misc {
     ignore_failures: Option<Vec<Step>>,
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
-    remote_topgrades: Option<Vec<String>>,
+    remote_topgrades: Option<Vec<RemoteTopgrade>>,
 
     remote_topgrade_path: Option<String>,
 
+    home_manager_flake: Option<String>,
+
+    remote_topgrade_parallel: Option<usize>,
+
+    remote_install_missing: Option<bool>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     ssh_arguments: Option<String>,
 
+    ssh_command: Option<String>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     tmux_arguments: Option<String>,
 
@@ -394,6 +750,20 @@ pub struct Misc {
     no_self_update: Option<bool>,
 
     log_filters: Option<Vec<String>>,
+
+    powershell_update_help: Option<bool>,
+
+    powershell_use_psresourceget: Option<PsResourceGet>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    powershell_module_exclusions: Option<Vec<String>>,
+
+    powershell_execution_policy_bypass: Option<bool>,
+
+    powershell_parallel: Option<u32>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    powershell_custom_commands: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Default, Debug, Merge)]
@@ -436,6 +806,18 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     windows: Option<Windows>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    macos: Option<MacOS>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    freebsd: Option<FreeBSD>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    netbsd: Option<NetBSD>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    illumos: Option<Illumos>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     npm: Option<NPM>,
 
@@ -746,6 +1128,10 @@ pub struct CommandLineArgs {
     #[clap(long = "show-skipped")]
     show_skipped: bool,
 
+    /// Write the result of each step to this file as JSON
+    #[clap(long = "report-json", value_name = "PATH")]
+    report_json: Option<PathBuf>,
+
     /// Tracing filter directives.
     ///
     /// See: https://docs.rs/tracing-subscriber/latest/tracing_subscriber/struct.EnvFilter.html
@@ -763,6 +1149,10 @@ pub struct CommandLineArgs {
     /// Don't update Topgrade
     #[clap(long = "no-self-update")]
     pub no_self_update: bool,
+
+    /// Upgrade Arch Linux even if there are unread news
+    #[clap(long = "ignore-news")]
+    ignore_news: bool,
 }
 
 impl CommandLineArgs {
@@ -917,6 +1307,11 @@ impl Config {
                 .unwrap_or(false)
     }
 
+    /// Tell whether unread Arch Linux news should be ignored.
+    pub fn ignore_news(&self) -> bool {
+        self.opt.ignore_news
+    }
+
     /// Tell whether we should run in tmux.
     pub fn run_in_tmux(&self) -> bool {
         self.opt.run_in_tmux
@@ -956,13 +1351,30 @@ impl Config {
     }
 
     /// List of remote hosts to run Topgrade in
-    pub fn remote_topgrades(&self) -> Option<&Vec<String>> {
+    pub fn remote_topgrades(&self) -> Option<&Vec<RemoteTopgrade>> {
         self.config_file
             .misc
             .as_ref()
             .and_then(|misc| misc.remote_topgrades.as_ref())
     }
 
+    /// The number of remote hosts upgraded at the same time
+    pub fn remote_topgrade_parallel(&self) -> Option<usize> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.remote_topgrade_parallel)
+    }
+
+    /// Whether to install Topgrade on the remote hosts that don't have it
+    pub fn remote_install_missing(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.remote_install_missing)
+            .unwrap_or(false)
+    }
+
     /// Path to Topgrade executable used for all remote hosts
     pub fn remote_topgrade_path(&self) -> &str {
         self.config_file
@@ -980,6 +1392,14 @@ impl Config {
             .and_then(|misc| misc.ssh_arguments.as_ref())
     }
 
+    /// The command that connects to the remote hosts
+    pub fn ssh_command(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.ssh_command.as_deref())
+    }
+
     /// Extra Git arguments
     pub fn git_arguments(&self) -> Option<&String> {
         self.config_file.git.as_ref().and_then(|git| git.arguments.as_ref())
@@ -1004,6 +1424,14 @@ impl Config {
             .with_context(|| format!("Failed to parse `tmux_arguments`: `{args}`"))
     }
 
+    /// Where to write the result of each step as JSON
+    pub fn report_json(&self) -> Option<PathBuf> {
+        self.opt
+            .report_json
+            .clone()
+            .or_else(|| env::var_os("TOPGRADE_REPORT_JSON").map(PathBuf::from))
+    }
+
     /// Prompt for a key before exiting
     pub fn keep_at_end(&self) -> bool {
         self.opt.keep_at_end || env::var("TOPGRADE_KEEP_END").is_ok()
@@ -1053,135 +1481,433 @@ impl Config {
             .unwrap_or("stable")
     }
 
-    /// Whether to accept all Windows updates
-    pub fn accept_all_windows_updates(&self) -> bool {
+    /// Whether to run `Update-Help` in the Powershell step
+    pub fn powershell_update_help(&self) -> bool {
         self.config_file
-            .windows
+            .misc
             .as_ref()
-            .and_then(|windows| windows.accept_all_updates)
-            .unwrap_or(true)
+            .and_then(|misc| misc.powershell_update_help)
+            .unwrap_or(false)
     }
 
-    /// Whether to self rename the Topgrade executable during the run
-    pub fn self_rename(&self) -> bool {
+    /// Whether Powershell modules are updated with PSResourceGet instead of PowerShellGet
+    pub fn powershell_use_psresourceget(&self) -> PsResourceGet {
         self.config_file
-            .windows
+            .misc
             .as_ref()
-            .and_then(|w| w.self_rename)
-            .unwrap_or(false)
+            .and_then(|misc| misc.powershell_use_psresourceget)
+            .unwrap_or(PsResourceGet::Auto)
     }
 
-    // Should wsl --update should use the --pre-release flag
-    pub fn wsl_update_pre_release(&self) -> bool {
+    /// Glob patterns of the Powershell modules that should not be updated
+    pub fn powershell_module_exclusions(&self) -> &[String] {
         self.config_file
-            .windows
+            .misc
             .as_ref()
-            .and_then(|w| w.wsl_update_pre_release)
-            .unwrap_or(false)
+            .and_then(|misc| misc.powershell_module_exclusions.as_deref())
+            .unwrap_or_default()
     }
 
-    // Should wsl --update use the --web-download flag
-    pub fn wsl_update_use_web_download(&self) -> bool {
+    /// Whether to launch Powershell with `-ExecutionPolicy Bypass`
+    pub fn powershell_execution_policy_bypass(&self) -> bool {
         self.config_file
-            .windows
+            .misc
             .as_ref()
-            .and_then(|w| w.wsl_update_use_web_download)
+            .and_then(|misc| misc.powershell_execution_policy_bypass)
             .unwrap_or(false)
     }
 
-    /// Whether Brew cask should be greedy
-    pub fn brew_cask_greedy(&self) -> bool {
+    /// How many Powershell modules are updated at the same time on pwsh 7 and later
+    pub fn powershell_parallel(&self) -> u32 {
         self.config_file
-            .brew
+            .misc
             .as_ref()
-            .and_then(|c| c.greedy_cask)
-            .unwrap_or(false)
+            .and_then(|misc| misc.powershell_parallel)
+            .unwrap_or(4)
     }
 
-    /// Whether Brew cask should be greedy_latest
-    pub fn brew_greedy_latest(&self) -> bool {
+    /// Powershell commands run after updating the modules
+    pub fn powershell_custom_commands(&self) -> &[String] {
         self.config_file
-            .brew
+            .misc
             .as_ref()
-            .and_then(|c| c.greedy_latest)
-            .unwrap_or(false)
+            .and_then(|misc| misc.powershell_custom_commands.as_deref())
+            .unwrap_or_default()
     }
 
-    /// Whether Brew should autoremove
-    pub fn brew_autoremove(&self) -> bool {
+    /// Whether to accept all Windows updates
+    pub fn accept_all_windows_updates(&self) -> bool {
         self.config_file
-            .brew
+            .windows
             .as_ref()
-            .and_then(|c| c.autoremove)
-            .unwrap_or(false)
+            .and_then(|windows| windows.accept_all_updates)
+            .unwrap_or(true)
     }
 
-    /// Whether Brew should upgrade formulae built from the HEAD branch
-    pub fn brew_fetch_head(&self) -> bool {
+    /// Whether to skip the driver updates offered by Windows Update
+    pub fn windows_update_exclude_drivers(&self) -> bool {
         self.config_file
-            .brew
+            .windows
             .as_ref()
-            .and_then(|c| c.fetch_head)
+            .and_then(|windows| windows.windows_update_exclude_drivers)
             .unwrap_or(false)
     }
 
-    /// Whether Composer should update itself
-    pub fn composer_self_update(&self) -> bool {
+    /// The KB articles that should not be installed by Windows Update
+    pub fn windows_update_excluded_kbs(&self) -> &[String] {
         self.config_file
-            .composer
+            .windows
             .as_ref()
-            .and_then(|c| c.self_update)
-            .unwrap_or(false)
+            .and_then(|windows| windows.windows_update_excluded_kbs.as_deref())
+            .unwrap_or_default()
     }
 
-    /// Whether to force plug update in Vim
-    pub fn force_vim_plug_update(&self) -> bool {
+    /// Whether to reboot after installing Windows updates
+    pub fn windows_updates_auto_reboot(&self) -> UpdatesAutoReboot {
         self.config_file
-            .vim
+            .windows
             .as_ref()
-            .and_then(|c| c.force_plug_update)
-            .unwrap_or_default()
+            .and_then(|windows| windows.windows_updates_auto_reboot)
+            .unwrap_or(UpdatesAutoReboot::Ask)
     }
 
-    /// Whether to send a desktop notification at the beginning of every step
-    pub fn notify_each_step(&self) -> bool {
+    /// Whether to skip the Windows Update and Microsoft Store steps when a reboot is pending
+    pub fn abort_on_pending_reboot(&self) -> bool {
         self.config_file
-            .misc
+            .windows
             .as_ref()
-            .and_then(|misc| misc.notify_each_step)
+            .and_then(|windows| windows.abort_on_pending_reboot)
             .unwrap_or(false)
     }
 
-    /// Extra garuda-update arguments
-    pub fn garuda_update_arguments(&self) -> &str {
+    /// Whether to update the Microsoft Store apps with winget when the MDM bridge fails
+    pub fn microsoft_store_fallback_winget(&self) -> bool {
         self.config_file
-            .linux
+            .windows
             .as_ref()
-            .and_then(|s| s.garuda_update_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|windows| windows.microsoft_store_fallback_winget)
+            .unwrap_or(true)
     }
 
-    /// Extra trizen arguments
-    pub fn trizen_arguments(&self) -> &str {
+    /// Whether to run Topgrade inside the WSL distributions that have it installed
+    pub fn wsl_run_topgrade(&self) -> bool {
         self.config_file
-            .linux
+            .windows
             .as_ref()
-            .and_then(|s| s.trizen_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|windows| windows.wsl_run_topgrade)
+            .unwrap_or(true)
     }
 
-    /// Extra Pikaur arguments
-    #[allow(dead_code)]
-    pub fn pikaur_arguments(&self) -> &str {
+    /// The WSL distributions to upgrade, all of them when empty
+    pub fn wsl_distributions(&self) -> &[String] {
         self.config_file
-            .linux
+            .windows
             .as_ref()
-            .and_then(|s| s.pikaur_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|windows| windows.wsl_distributions.as_deref())
+            .unwrap_or_default()
     }
 
-    /// Extra Pamac arguments
-    pub fn pamac_arguments(&self) -> &str {
+    /// Whether the Office updater shows its user interface
+    pub fn office_display_level(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.office_display_level)
+            .unwrap_or(false)
+    }
+
+    /// Whether the Office updater closes the running Office applications
+    pub fn office_force_app_shutdown(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.office_force_app_shutdown)
+            .unwrap_or(false)
+    }
+
+    /// Whether the component store cleanup also removes the superseded versions of components
+    pub fn windows_resetbase(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.windows_resetbase)
+            .unwrap_or(false)
+    }
+
+    /// Extra arguments passed to `choco upgrade all`
+    pub fn choco_arguments(&self) -> Option<&str> {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.choco_arguments.as_deref())
+    }
+
+    /// Extra arguments passed to `winget upgrade`
+    pub fn winget_arguments(&self) -> Option<&str> {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.winget_arguments.as_deref())
+    }
+
+    /// Extra arguments passed to `scoop update *`
+    pub fn scoop_arguments(&self) -> Option<&str> {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.scoop_arguments.as_deref())
+    }
+
+    /// Whether winget upgrades the packages with an unknown version
+    pub fn winget_include_unknown(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.winget_include_unknown)
+            .unwrap_or(false)
+    }
+
+    /// Whether winget upgrades the pinned packages
+    pub fn winget_use_pinned(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.winget_use_pinned)
+            .unwrap_or(false)
+    }
+
+    /// The winget sources to upgrade, all of them when empty
+    pub fn winget_sources(&self) -> &[String] {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.winget_sources.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to self rename the Topgrade executable during the run
+    pub fn self_rename(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.self_rename)
+            .unwrap_or(false)
+    }
+
+    // Should wsl --update should use the --pre-release flag
+    pub fn wsl_update_pre_release(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.wsl_update_pre_release)
+            .unwrap_or(false)
+    }
+
+    // Should wsl --update use the --web-download flag
+    pub fn wsl_update_use_web_download(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.wsl_update_use_web_download)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew cask should be greedy
+    pub fn brew_cask_greedy(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.greedy_cask)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew cask should be greedy_latest
+    pub fn brew_greedy_latest(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.greedy_latest)
+            .unwrap_or(false)
+    }
+
+    /// The casks upgraded with `--greedy` even though Brew cask isn't greedy
+    pub fn brew_greedy_casks(&self) -> &[String] {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.greedy_casks.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The Homebrew prefixes to update, detected when not set
+    pub fn brew_prefixes(&self) -> Option<&[String]> {
+        self.config_file.brew.as_ref().and_then(|c| c.prefixes.as_deref())
+    }
+
+    /// The command upgrading the casks
+    pub fn brew_cask_updater(&self) -> BrewCaskUpdater {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.cask_updater)
+            .unwrap_or(BrewCaskUpdater::Auto)
+    }
+
+    /// Whether Brew should autoremove
+    pub fn brew_autoremove(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.autoremove)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew should upgrade formulae built from the HEAD branch
+    pub fn brew_fetch_head(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.fetch_head)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew should restart the started services of the upgraded formulae
+    pub fn brew_restart_services(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.restart_services)
+            .unwrap_or(false)
+    }
+
+    /// Whether to install only the recommended macOS updates
+    pub fn macos_recommended_only(&self) -> bool {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.macos_recommended_only)
+            .unwrap_or(false)
+    }
+
+    /// Whether to install the upgrades to a new major version of macOS
+    pub fn macos_install_os_upgrades(&self) -> bool {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.macos_install_os_upgrades)
+            .unwrap_or(false)
+    }
+
+    /// Whether to restart when a macOS update requires it
+    pub fn macos_restart(&self) -> MacOSRestart {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.macos_restart)
+            .unwrap_or(MacOSRestart::Never)
+    }
+
+    /// Whether `xcodes` should install new Xcode releases without asking
+    pub fn xcodes_install_latest(&self) -> bool {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.xcodes_install_latest)
+            .unwrap_or(false)
+    }
+
+    /// The App Store apps that `mas` shouldn't upgrade
+    pub fn mas_excluded_apps(&self) -> &[u64] {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.mas_excluded_apps.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to upgrade the packages of the running FreeBSD jails
+    pub fn freebsd_update_jails(&self) -> bool {
+        self.config_file
+            .freebsd
+            .as_ref()
+            .and_then(|freebsd| freebsd.freebsd_update_jails)
+            .unwrap_or(false)
+    }
+
+    /// Whether to rebuild the NetBSD packages from pkgsrc instead of upgrading them with pkgin
+    pub fn netbsd_build_from_source(&self) -> bool {
+        self.config_file
+            .netbsd
+            .as_ref()
+            .and_then(|netbsd| netbsd.netbsd_build_from_source)
+            .unwrap_or(false)
+    }
+
+    /// Extra arguments passed to `pkg update` on illumos
+    pub fn illumos_pkg_arguments(&self) -> Option<&str> {
+        self.config_file
+            .illumos
+            .as_ref()
+            .and_then(|illumos| illumos.pkg_arguments.as_deref())
+    }
+
+    /// Whether Composer should update itself
+    pub fn composer_self_update(&self) -> bool {
+        self.config_file
+            .composer
+            .as_ref()
+            .and_then(|c| c.self_update)
+            .unwrap_or(false)
+    }
+
+    /// Whether to force plug update in Vim
+    pub fn force_vim_plug_update(&self) -> bool {
+        self.config_file
+            .vim
+            .as_ref()
+            .and_then(|c| c.force_plug_update)
+            .unwrap_or_default()
+    }
+
+    /// Whether to send a desktop notification at the beginning of every step
+    pub fn notify_each_step(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.notify_each_step)
+            .unwrap_or(false)
+    }
+
+    /// Extra garuda-update arguments
+    pub fn garuda_update_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.garuda_update_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Extra trizen arguments
+    pub fn trizen_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.trizen_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Extra Pikaur arguments
+    #[allow(dead_code)]
+    pub fn pikaur_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.pikaur_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Extra Pamac arguments
+    pub fn pamac_arguments(&self) -> &str {
         self.config_file
             .linux
             .as_ref()
@@ -1198,46 +1924,136 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// Abort the Arch Linux upgrade when there are unread news
+    pub fn arch_news_check(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.arch_news_check)
+            .unwrap_or(false)
+    }
+
+    /// The number of versions of each package paccache keeps in the cache on cleanup
+    pub fn paccache_keep(&self) -> Option<u32> {
+        self.config_file.linux.as_ref().and_then(|linux| linux.paccache_keep)
+    }
+
+    /// Sync the pacman files database used by `pacman -F`
+    pub fn pacman_files_db(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.pacman_files_db)
+            .unwrap_or(false)
+    }
+
     /// Get the package manager of an Arch Linux system
     pub fn arch_package_manager(&self) -> ArchPackageManager {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.arch_package_manager)
-            .unwrap_or(ArchPackageManager::Autodetect)
+            .and_then(|s| s.arch_package_manager)
+            .unwrap_or(ArchPackageManager::Autodetect)
+    }
+
+    /// Extra yay arguments
+    pub fn yay_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.yay_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Extra aura arguments for AUR and pacman
+    pub fn aura_aur_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.aura_aur_arguments.as_deref())
+            .unwrap_or("")
+    }
+    pub fn aura_pacman_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.aura_pacman_arguments.as_deref())
+            .unwrap_or("")
+    }
+
+    /// Refresh the pacman mirrorlist before upgrading
+    pub fn refresh_mirrors(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.refresh_mirrors)
+            .unwrap_or(false)
+    }
+
+    /// Arguments to pass reflector when refreshing the mirrorlist
+    pub fn reflector_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.reflector_arguments.as_deref())
+            .unwrap_or("--protocol https --latest 20 --sort rate")
+    }
+
+    /// Extra apt arguments
+    pub fn apt_arguments(&self) -> Option<&str> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_arguments.as_deref())
+    }
+
+    /// The apt subcommand used to upgrade packages
+    pub fn apt_dist_upgrade_mode(&self) -> AptUpgradeMode {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_dist_upgrade_mode)
+            .unwrap_or(AptUpgradeMode::DistUpgrade)
     }
 
-    /// Extra yay arguments
-    pub fn yay_arguments(&self) -> &str {
+    /// Ask for confirmation when an apt upgrade would remove more packages than this
+    pub fn apt_max_removals(&self) -> Option<usize> {
+        self.config_file.linux.as_ref().and_then(|linux| linux.apt_max_removals)
+    }
+
+    /// The apt subcommand used to upgrade packages on MX Linux and antiX
+    pub fn mx_apt_command(&self) -> AptUpgradeMode {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.yay_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|linux| linux.mx_apt_command)
+            .unwrap_or_else(|| self.apt_dist_upgrade_mode())
     }
 
-    /// Extra aura arguments for AUR and pacman
-    pub fn aura_aur_arguments(&self) -> &str {
+    /// Use `pop-upgrade` in addition to apt on Pop!_OS
+    pub fn pop_upgrade(&self) -> bool {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.aura_aur_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|linux| linux.pop_upgrade)
+            .unwrap_or(true)
     }
-    pub fn aura_pacman_arguments(&self) -> &str {
+
+    /// Upgrade the Pop!_OS recovery partition with `pop-upgrade`
+    pub fn pop_upgrade_recovery(&self) -> bool {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|s| s.aura_pacman_arguments.as_deref())
-            .unwrap_or("")
+            .and_then(|linux| linux.pop_upgrade_recovery)
+            .unwrap_or(false)
     }
 
-    /// Extra apt arguments
-    pub fn apt_arguments(&self) -> Option<&str> {
+    /// Extra apk arguments
+    pub fn apk_arguments(&self) -> Option<&str> {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|linux| linux.apt_arguments.as_deref())
+            .and_then(|linux| linux.apk_arguments.as_deref())
     }
 
     /// Extra dnf arguments
@@ -1256,6 +2072,24 @@ impl Config {
             .and_then(|linux| linux.nix_arguments.as_deref())
     }
 
+    /// Path to the flake of a flake-based NixOS system
+    pub fn nixos_flake_path(&self) -> Option<String> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.nixos_flake_path.as_deref())
+            .map(|path| shellexpand::tilde(path).into_owned())
+    }
+
+    /// The `nixos-rebuild` action used to upgrade NixOS
+    pub fn nixos_rebuild_action(&self) -> NixosRebuildAction {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.nixos_rebuild_action)
+            .unwrap_or(NixosRebuildAction::Switch)
+    }
+
     /// Extra nix-env arguments
     pub fn nix_env_arguments(&self) -> Option<&str> {
         self.config_file
@@ -1264,6 +2098,103 @@ impl Config {
             .and_then(|linux| linux.nix_env_arguments.as_deref())
     }
 
+    /// How needrestart restarts the services using outdated libraries
+    pub fn needrestart_mode(&self) -> Option<NeedrestartMode> {
+        self.config_file.linux.as_ref().and_then(|linux| linux.needrestart_mode)
+    }
+
+    /// The package manager used to upgrade an unknown distribution
+    pub fn fallback_package_manager(&self) -> Option<FallbackPackageManager> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.fallback_package_manager)
+    }
+
+    /// Only download the KDE neon updates and install them on the next boot
+    pub fn neon_offline_updates(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.neon_offline_updates)
+            .unwrap_or(false)
+    }
+
+    /// The tool taking a filesystem snapshot around the system upgrade
+    pub fn pre_upgrade_snapshot(&self) -> PreUpgradeSnapshot {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.pre_upgrade_snapshot)
+            .unwrap_or(PreUpgradeSnapshot::None)
+    }
+
+    /// Continue with the system upgrade when the snapshot can't be taken
+    pub fn snapshot_failure_is_warning(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snapshot_failure_is_warning)
+            .unwrap_or(false)
+    }
+
+    /// Report the systemd units that failed during the run in the summary
+    pub fn check_failed_units(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.check_failed_units)
+            .unwrap_or(false)
+    }
+
+    /// The number of revisions of each snap kept on cleanup, including the active one
+    pub fn snap_retain(&self) -> usize {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snap_retain)
+            .unwrap_or(1)
+    }
+
+    /// The AppImages updated by AM and appman, all of them when unset
+    pub fn am_apps(&self) -> Option<&Vec<String>> {
+        self.config_file.linux.as_ref().and_then(|linux| linux.am_apps.as_ref())
+    }
+
+    /// Extra arguments passed to `pacstall -Up`
+    pub fn pacstall_args(&self) -> Option<&str> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.pacstall_args.as_deref())
+    }
+
+    /// The Toolbx containers to update, all of them when unset
+    pub fn toolbx_containers(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.toolbx_containers.as_ref())
+    }
+
+    /// Start the Waydroid session again after upgrading, if it was running before
+    pub fn waydroid_restart_session(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.waydroid_restart_session)
+            .unwrap_or(false)
+    }
+
+    /// Generations older than this are deleted by `nix-collect-garbage` on cleanup
+    pub fn nix_gc_age(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.nix_gc_age.as_deref())
+            .unwrap_or("30d")
+    }
+
     /// Extra Home Manager arguments
     pub fn home_manager(&self) -> Option<&Vec<String>> {
         self.config_file
@@ -1272,6 +2203,24 @@ impl Config {
             .and_then(|misc| misc.home_manager_arguments.as_ref())
     }
 
+    /// Directory of the flake holding a standalone home-manager configuration
+    pub fn home_manager_flake(&self) -> Option<String> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.home_manager_flake.as_deref())
+            .map(|path| shellexpand::tilde(path).into_owned())
+    }
+
+    /// Path to the Guix System configuration file
+    pub fn guix_system_config(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.guix_system_config.as_deref())
+            .unwrap_or("/etc/config.scm")
+    }
+
     /// Distrobox use root
     pub fn distrobox_root(&self) -> bool {
         self.config_file
@@ -1286,6 +2235,11 @@ impl Config {
         self.config_file.distrobox.as_ref().and_then(|r| r.containers.as_ref())
     }
 
+    /// The number of Distrobox containers upgraded at the same time
+    pub fn distrobox_parallel(&self) -> Option<usize> {
+        self.config_file.distrobox.as_ref().and_then(|r| r.parallel)
+    }
+
     /// Concurrency limit for git
     pub fn git_concurrency_limit(&self) -> Option<usize> {
         self.config_file.git.as_ref().and_then(|git| git.max_concurrency)
@@ -1321,6 +2275,15 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Run `swupd repair --quick` after updating Clear Linux
+    pub fn clearlinux_repair(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.clearlinux_repair)
+            .unwrap_or(false)
+    }
+
     /// Use distro-sync in Red Hat based distributions
     pub fn redhat_distro_sync(&self) -> bool {
         self.config_file
@@ -1330,15 +2293,62 @@ impl Config {
             .unwrap_or(false)
     }
 
-    /// Use zypper dist-upgrade (same as distro-sync on RH) instead of update (default: false on SLE/Leap, ignored on Tumbleweed (dup is always ran))
-    pub fn suse_dup(&self) -> bool {
+    /// Stage dnf updates to be applied on the next reboot instead of applying them live
+    pub fn dnf_offline(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.dnf_offline)
+            .unwrap_or(false)
+    }
+
+    /// Reboot to apply staged dnf offline updates
+    pub fn dnf_offline_reboot(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.dnf_offline_reboot)
+            .unwrap_or(false)
+    }
+
+    /// Check whether a reboot is required after a dnf upgrade
+    pub fn dnf_needs_restarting(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.dnf_needs_restarting)
+            .unwrap_or(true)
+    }
+
+    /// Upgrade to the latest Amazon Linux 2023 release when a newer one is available
+    pub fn amazon_linux_release_upgrade(&self) -> bool {
         self.config_file
             .linux
             .as_ref()
-            .and_then(|linux| linux.suse_dup)
+            .and_then(|linux| linux.amazon_linux_release_upgrade)
             .unwrap_or(false)
     }
 
+    /// The zypper subcommand used to upgrade openSUSE and SLE
+    ///
+    /// Falls back to `suse_dup`, returns `None` when neither is set.
+    pub fn suse_mode(&self) -> Option<SuseMode> {
+        let linux = self.config_file.linux.as_ref()?;
+        linux.suse_mode.or(match linux.suse_dup {
+            Some(true) => Some(SuseMode::DistUpgrade),
+            _ => None,
+        })
+    }
+
+    /// Whether to reboot into the new snapshot created by `transactional-update`
+    pub fn transactional_update_reboot(&self) -> TransactionalUpdateReboot {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.transactional_update_reboot)
+            .unwrap_or(TransactionalUpdateReboot::No)
+    }
+
     /// Use rpm-ostree in *when rpm-ostree is detected* (default: true)
     pub fn rpm_ostree(&self) -> bool {
         self.config_file
@@ -1348,6 +2358,113 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Apply rpm-ostree upgrades to the running system with `--apply-live`
+    pub fn rpm_ostree_apply_live(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.rpm_ostree_apply_live)
+            .unwrap_or(false)
+    }
+
+    /// Use bootc to upgrade image based systems when bootc is detected
+    pub fn bootc(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.bootc)
+            .unwrap_or(false)
+    }
+
+    /// Arguments to pass `cave resolve world` on Exherbo
+    pub fn cave_resolve_arguments(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.cave_resolve_arguments.as_deref())
+            .unwrap_or("-c1 -Cs -km -Km -x")
+    }
+
+    /// Run `cave purge` on cleanup
+    pub fn cave_purge(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.cave_purge)
+            .unwrap_or(true)
+    }
+
+    /// Run `cave fix-linkage` after upgrading Exherbo
+    pub fn cave_fix_linkage(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.cave_fix_linkage)
+            .unwrap_or(true)
+    }
+
+    /// Run `eclectic config interactive` after upgrading Exherbo
+    pub fn eclectic_config(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.eclectic_config)
+            .unwrap_or(true)
+    }
+
+    /// Rebuild live ebuilds with `smart-live-rebuild`
+    pub fn gentoo_live_rebuild(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.gentoo_live_rebuild)
+            .unwrap_or(false)
+    }
+
+    /// Extra arguments for `smart-live-rebuild`
+    pub fn gentoo_live_rebuild_args(&self) -> Option<&str> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.gentoo_live_rebuild_args.as_deref())
+    }
+
+    /// Run `emerge --depclean` on cleanup
+    pub fn emerge_depclean(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.emerge_depclean)
+            .unwrap_or(true)
+    }
+
+    /// Run `emerge @preserved-rebuild` on cleanup when libraries were preserved
+    pub fn emerge_preserved_rebuild(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.emerge_preserved_rebuild)
+            .unwrap_or(true)
+    }
+
+    /// Check the system for GLSAs with `glsa-check`
+    pub fn glsa_check(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.glsa_check)
+            .unwrap_or(false)
+    }
+
+    /// Use `uupd` or `ublue-update` instead of rpm-ostree on Universal Blue images (default: true)
+    pub fn ublue_update(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.ublue_update)
+            .unwrap_or(true)
+    }
+
     /// Determine if we should ignore failures for this step
     pub fn ignore_failure(&self, step: Step) -> bool {
         self.config_file
@@ -1405,6 +2522,14 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// The program used to run commands as administrator on Windows, overriding the detection
+    pub fn windows_sudo(&self) -> Option<WindowsSudo> {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|windows| windows.windows_sudo)
+    }
+
     pub fn sudo_command(&self) -> Option<SudoKind> {
         self.config_file.misc.as_ref().and_then(|misc| misc.sudo_command)
     }
@@ -1445,15 +2570,70 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Apply Raspberry Pi bootloader EEPROM updates instead of only reporting them
+    pub fn rpi_eeprom_apply(&self) -> bool {
+        self.config_file
+            .firmware
+            .as_ref()
+            .and_then(|firmware| firmware.rpi_eeprom_apply)
+            .unwrap_or(false)
+    }
+
+    /// Devices which firmware is not updated, matched by GUID, device ID or a part of their name
+    pub fn fwupd_exclude_devices(&self) -> &[String] {
+        self.config_file
+            .firmware
+            .as_ref()
+            .and_then(|firmware| firmware.exclude_devices.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether to use sudo for the system-wide installations, `None` when unset
+    #[cfg(target_os = "linux")]
+    pub fn flatpak_use_sudo(&self) -> Option<bool> {
+        self.config_file.flatpak.as_ref().and_then(|flatpak| flatpak.use_sudo)
+    }
+
+    /// Only update the user installation of Flatpak
+    #[cfg(target_os = "linux")]
+    pub fn flatpak_user_only(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.user_only)
+            .unwrap_or(false)
+    }
+
+    /// Only update the system-wide installations of Flatpak
+    #[cfg(target_os = "linux")]
+    pub fn flatpak_system_only(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.system_only)
+            .unwrap_or(false)
+    }
+
+    /// Run `flatpak repair` before updating
     #[cfg(target_os = "linux")]
-    pub fn flatpak_use_sudo(&self) -> bool {
+    pub fn flatpak_repair(&self) -> bool {
         self.config_file
             .flatpak
             .as_ref()
-            .and_then(|flatpak| flatpak.use_sudo)
+            .and_then(|flatpak| flatpak.repair)
             .unwrap_or(false)
     }
 
+    /// Custom system-wide Flatpak installations to update
+    #[cfg(target_os = "linux")]
+    pub fn flatpak_installations(&self) -> &[String] {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.installations.as_deref())
+            .unwrap_or_default()
+    }
+
     #[cfg(target_os = "linux")]
     str_value!(linux, emerge_sync_flags);
 
@@ -1539,6 +2719,55 @@ mod test {
         assert!(toml::from_str::<ConfigFile>(str).is_ok());
     }
 
+    #[test]
+    fn test_remote_topgrades_hostnames() {
+        let config_file: ConfigFile =
+            toml::from_str("[misc]\nremote_topgrades = [\"toothless\", \"user@pi\"]").unwrap();
+        let remotes = config_file.misc.unwrap().remote_topgrades.unwrap();
+
+        assert_eq!(
+            remotes.iter().map(|remote| remote.host.as_str()).collect::<Vec<_>>(),
+            ["toothless", "user@pi"]
+        );
+        assert!(remotes
+            .iter()
+            .all(|remote| remote.args.is_empty() && remote.env.is_empty() && remote.ssh_args.is_empty()));
+    }
+
+    #[test]
+    fn test_remote_topgrades_tables() {
+        let str = r#"
+            [misc]
+            remote_topgrades = [
+                "toothless",
+                { host = "server1", args = ["--disable", "containers"], env = { FOO = "bar" }, ssh_args = ["-p", "2222"] },
+                { host = "server2" },
+            ]
+        "#;
+        let config_file: ConfigFile = toml::from_str(str).unwrap();
+        let remotes = config_file.misc.unwrap().remote_topgrades.unwrap();
+
+        assert_eq!(remotes.len(), 3);
+        assert_eq!(remotes[0].host, "toothless");
+        assert_eq!(
+            remotes[1],
+            RemoteTopgrade {
+                host: String::from("server1"),
+                args: vec![String::from("--disable"), String::from("containers")],
+                env: BTreeMap::from([(String::from("FOO"), String::from("bar"))]),
+                ssh_args: vec![String::from("-p"), String::from("2222")],
+            }
+        );
+        assert_eq!(remotes[2].host, "server2");
+        assert!(remotes[2].args.is_empty());
+    }
+
+    #[test]
+    fn test_remote_topgrades_unknown_field() {
+        let str = "[misc]\nremote_topgrades = [{ host = \"server1\", arguments = [\"--yes\"] }]";
+        assert!(toml::from_str::<ConfigFile>(str).is_err());
+    }
+
     fn config() -> Config {
         Config {
             opt: CommandLineArgs::parse_from::<_, String>([]),
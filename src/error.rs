@@ -10,9 +10,12 @@ pub enum TopgradeError {
     #[error("`{0}` failed: {1}")]
     ProcessFailedWithOutput(String, ExitStatus, String),
 
-    #[error("Unknown Linux Distribution")]
+    #[error(
+        "Unknown Linux Distribution (ID: {id}, ID_LIKE: {id_like}), \
+         set `fallback_package_manager` in the `linux` section of the configuration to upgrade it anyway"
+    )]
     #[cfg(target_os = "linux")]
-    UnknownLinuxDistribution,
+    UnknownLinuxDistribution { id: String, id_like: String },
 
     #[error("File \"/etc/os-release\" does not exist or is empty")]
     #[cfg(target_os = "linux")]
@@ -21,6 +24,10 @@ pub enum TopgradeError {
     #[error("Failed getting the system package manager")]
     #[cfg(target_os = "linux")]
     FailedGettingPackageManager,
+
+    #[error("The configured Arch package manager `{0}` is not available")]
+    #[cfg(target_os = "linux")]
+    MissingArchPackageManager(String),
 }
 
 #[derive(Error, Debug)]
@@ -1,9 +1,14 @@
 #![allow(dead_code)]
 use crate::executor::RunType;
+use crate::report::SummaryNote;
+#[cfg(target_os = "linux")]
+use crate::steps::os::linux::Distribution;
 use crate::sudo::Sudo;
 use crate::utils::{require_option, REQUIRE_SUDO};
 use crate::{config::Config, executor::Executor};
 use color_eyre::eyre::Result;
+#[cfg(target_os = "linux")]
+use once_cell::sync::OnceCell;
 use std::env::var;
 use std::path::Path;
 use std::sync::Mutex;
@@ -18,6 +23,14 @@ pub struct ExecutionContext<'a> {
     tmux_session: Mutex<Option<String>>,
     /// True if topgrade is running under ssh.
     under_ssh: bool,
+    /// The systemd units that were already failed before any step ran, when `check_failed_units` is set.
+    #[cfg(target_os = "linux")]
+    failed_units_baseline: Option<Vec<String>>,
+    /// The Linux distribution, detected on first use.
+    #[cfg(target_os = "linux")]
+    distribution: OnceCell<Result<Distribution>>,
+    /// The notes left by the steps for the summary, with the IDs they were left under.
+    summary_notes: Mutex<Vec<(String, SummaryNote)>>,
 }
 
 impl<'a> ExecutionContext<'a> {
@@ -29,6 +42,13 @@ impl<'a> ExecutionContext<'a> {
             config,
             tmux_session: Mutex::new(None),
             under_ssh,
+            #[cfg(target_os = "linux")]
+            failed_units_baseline: config
+                .check_failed_units()
+                .then(crate::steps::os::linux::failed_systemd_units),
+            #[cfg(target_os = "linux")]
+            distribution: OnceCell::new(),
+            summary_notes: Mutex::new(Vec::new()),
         }
     }
 
@@ -53,6 +73,17 @@ impl<'a> ExecutionContext<'a> {
         self.under_ssh
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn distribution(&self) -> &Result<Distribution> {
+        self.distribution
+            .get_or_init(|| Distribution::detect_with_fallback(self.config))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn failed_units_baseline(&self) -> Option<&Vec<String>> {
+        self.failed_units_baseline.as_ref()
+    }
+
     pub fn set_tmux_session(&self, session_name: String) {
         self.tmux_session.lock().unwrap().replace(session_name);
     }
@@ -60,4 +91,27 @@ impl<'a> ExecutionContext<'a> {
     pub fn get_tmux_session(&self) -> Option<String> {
         self.tmux_session.lock().unwrap().clone()
     }
+
+    /// Leaves a note for the summary. It replaces the note left under the same `id`, so that
+    /// repeated or retried steps don't repeat it.
+    pub fn set_summary_note(&self, id: &str, note: SummaryNote) {
+        let mut notes = self.summary_notes.lock().unwrap();
+        match notes.iter_mut().find(|(note_id, _)| note_id == id) {
+            Some((_, existing)) => *existing = note,
+            None => notes.push((id.to_string(), note)),
+        }
+    }
+
+    /// Removes the note left under `id`, when what it reported was undone.
+    pub fn remove_summary_note(&self, id: &str) {
+        self.summary_notes.lock().unwrap().retain(|(note_id, _)| note_id != id);
+    }
+
+    /// Takes the notes left for the summary, in the order they were left.
+    pub fn take_summary_notes(&self) -> Vec<SummaryNote> {
+        std::mem::take(&mut *self.summary_notes.lock().unwrap())
+            .into_iter()
+            .map(|(_, note)| note)
+            .collect()
+    }
 }
@@ -1,4 +1,5 @@
 //! Utilities for command execution
+use std::cell::Cell;
 use std::ffi::{OsStr, OsString};
 use std::path::Path;
 use std::process::{Child, Command, ExitStatus, Output};
@@ -177,17 +178,29 @@ impl Executor {
     /// that can indicate success of a script
     #[allow(dead_code)]
     pub fn status_checked_with_codes(&mut self, codes: &[i32]) -> Result<()> {
+        self.status_code_checked_with_codes(codes).map(|_| ())
+    }
+
+    /// Same as `status_checked_with_codes`, but returns the exit code of the command.
+    /// The exit code is `None` on dry runs.
+    #[allow(dead_code)]
+    pub fn status_code_checked_with_codes(&mut self, codes: &[i32]) -> Result<Option<i32>> {
         match self {
-            Executor::Wet(c) => c.status_checked_with(|status| {
-                if status.success() || status.code().as_ref().map(|c| codes.contains(c)).unwrap_or(false) {
-                    Ok(())
-                } else {
-                    Err(())
-                }
-            }),
+            Executor::Wet(c) => {
+                let code = Cell::new(None);
+                c.status_checked_with(|status| {
+                    code.set(status.code());
+                    if status.success() || status.code().as_ref().map(|c| codes.contains(c)).unwrap_or(false) {
+                        Ok(())
+                    } else {
+                        Err(())
+                    }
+                })?;
+                Ok(code.get())
+            }
             Executor::Dry(c) => {
                 c.dry_run();
-                Ok(())
+                Ok(None)
             }
         }
     }
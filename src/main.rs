@@ -1,5 +1,6 @@
 #![allow(clippy::cognitive_complexity)]
 
+use std::cell::RefCell;
 use std::env;
 use std::io;
 use std::path::PathBuf;
@@ -123,15 +124,15 @@ fn run() -> Result<()> {
         }
     }
 
-    let powershell = powershell::Powershell::new();
+    let powershell = powershell::Powershell::new(&config);
     let should_run_powershell = powershell.profile().is_some() && config.should_run(Step::Powershell);
     let emacs = emacs::Emacs::new();
-    #[cfg(target_os = "linux")]
-    let distribution = linux::Distribution::detect();
 
-    let sudo = config.sudo_command().map_or_else(sudo::Sudo::detect, sudo::Sudo::new);
+    let sudo = sudo::Sudo::from_config(&config);
     let run_type = executor::RunType::new(config.dry_run());
     let ctx = execution_context::ExecutionContext::new(run_type, sudo, &config);
+    #[cfg(target_os = "linux")]
+    let distribution = ctx.distribution();
     let mut runner = runner::Runner::new(&ctx);
 
     // If
@@ -182,21 +183,69 @@ fn run() -> Result<()> {
     }
 
     if let Some(topgrades) = config.remote_topgrades() {
-        for remote_topgrade in topgrades.iter().filter(|t| config.should_execute_remote(hostname(), t)) {
-            runner.execute(Step::Remotes, format!("Remote ({remote_topgrade})"), || {
-                ssh::ssh_step(&ctx, remote_topgrade)
+        let remotes: Vec<_> = topgrades
+            .iter()
+            .filter(|t| config.should_execute_remote(hostname(), &t.host))
+            .collect();
+        let results = RefCell::new(ssh::ssh_parallel(&ctx, &remotes));
+        for remote_topgrade in remotes {
+            runner.execute(Step::Remotes, format!("Remote ({})", remote_topgrade.host), || {
+                // Retries, and the remotes that don't run concurrently, connect on their own
+                results
+                    .borrow_mut()
+                    .remove(&remote_topgrade.host)
+                    .unwrap_or_else(|| ssh::ssh_step(&ctx, remote_topgrade))
             })?;
         }
     }
 
     #[cfg(windows)]
     {
-        runner.execute(Step::Wsl, "WSL", || windows::run_wsl_topgrade(&ctx))?;
+        if config.should_run(Step::System) || config.should_run(Step::MicrosoftStore) {
+            windows::check_pending_reboot(&ctx);
+        }
+        if config.should_run(Step::Wsl) {
+            match windows::wsl_topgrade_distributions(&ctx) {
+                Ok(distributions) => {
+                    for distribution in distributions {
+                        runner.execute(Step::Wsl, format!("WSL Topgrade ({distribution})"), || {
+                            windows::run_wsl_topgrade(&ctx, &distribution)
+                        })?;
+                    }
+                }
+                Err(e) if e.downcast_ref::<error::SkipStep>().is_some() => debug!("{e}"),
+                Err(e) => runner.execute(Step::Wsl, "WSL Topgrade", || {
+                    Err(color_eyre::eyre::eyre!("Could not list the WSL distributions: {e:#}"))
+                })?,
+            }
+        }
         runner.execute(Step::WslUpdate, "WSL", || windows::update_wsl(&ctx))?;
+        if config.should_run(Step::WslPackages) {
+            match windows::wsl_package_distributions(&ctx) {
+                Ok(distributions) => {
+                    for distribution in distributions {
+                        runner.execute(Step::WslPackages, format!("WSL ({distribution})"), || {
+                            windows::upgrade_wsl_packages(&ctx, &distribution)
+                        })?;
+                    }
+                }
+                Err(e) if e.downcast_ref::<error::SkipStep>().is_some() => debug!("{e}"),
+                Err(e) => runner.execute(Step::WslPackages, "WSL", || {
+                    Err(color_eyre::eyre::eyre!("Could not list the WSL distributions: {e:#}"))
+                })?,
+            }
+        }
         runner.execute(Step::Chocolatey, "Chocolatey", || windows::run_chocolatey(&ctx))?;
         runner.execute(Step::Scoop, "Scoop", || windows::run_scoop(&ctx))?;
         runner.execute(Step::Winget, "Winget", || windows::run_winget(&ctx))?;
+        runner.execute(Step::MicrosoftStore, "Microsoft Store", || {
+            windows::microsoft_store(&ctx)
+        })?;
+        runner.execute(Step::Office, "Microsoft Office", || windows::run_office_update(&ctx))?;
         runner.execute(Step::System, "Windows update", || windows::windows_update(&ctx))?;
+        runner.execute(Step::Dism, "Component store cleanup", || {
+            windows::run_dism_cleanup(&ctx)
+        })?;
     }
 
     #[cfg(target_os = "linux")]
@@ -205,9 +254,15 @@ fn run() -> Result<()> {
         // by other package managers.
         runner.execute(Step::Shell, "packer.nu", || linux::run_packer_nu(&ctx))?;
 
-        match &distribution {
+        match distribution {
             Ok(distribution) => {
-                runner.execute(Step::System, "System update", || distribution.upgrade(&ctx))?;
+                runner.execute(Step::Mirrors, "Mirrors", || distribution.refresh_mirrors(&ctx))?;
+                runner.execute(Step::System, "System update", || {
+                    linux::with_upgrade_snapshot(&ctx, || distribution.upgrade(&ctx))
+                })?;
+            }
+            Err(_) if android::is_termux() => {
+                runner.execute(Step::Pkg, "Termux Packages", || android::upgrade_packages(&ctx))?;
             }
             Err(e) => {
                 println!("Error detecting current distribution: {e}");
@@ -223,13 +278,40 @@ fn run() -> Result<()> {
         runner.execute(Step::Pacstall, "pacstall", || linux::run_pacstall(&ctx))?;
         runner.execute(Step::Pacdef, "pacdef", || linux::run_pacdef(&ctx))?;
         runner.execute(Step::Protonup, "protonup", || linux::run_protonup_update(&ctx))?;
-        runner.execute(Step::Distrobox, "distrobox", || linux::run_distrobox_update(&ctx))?;
+        match config
+            .should_run(Step::Distrobox)
+            .then(|| linux::distrobox_parallel_containers(&ctx))
+            .flatten()
+        {
+            Some(containers) => {
+                let results = RefCell::new(linux::upgrade_distrobox_containers(&ctx, &containers));
+                for container in &containers {
+                    runner.execute(Step::Distrobox, format!("distrobox ({container})"), || {
+                        // Retries upgrade the container again, on its own
+                        results
+                            .borrow_mut()
+                            .remove(container)
+                            .unwrap_or_else(|| linux::upgrade_distrobox_container(&ctx, container))
+                    })?;
+                }
+            }
+            None => runner.execute(Step::Distrobox, "distrobox", || linux::run_distrobox_update(&ctx))?,
+        }
         runner.execute(Step::DkpPacman, "dkp-pacman", || linux::run_dkp_pacman_update(&ctx))?;
         runner.execute(Step::System, "pihole", || linux::run_pihole_update(&ctx))?;
         runner.execute(Step::Firmware, "Firmware upgrades", || linux::run_fwupdmgr(&ctx))?;
+        runner.execute(Step::Firmware, "Raspberry Pi EEPROM", || {
+            linux::run_rpi_eeprom_update(&ctx)
+        })?;
         runner.execute(Step::Restarts, "Restarts", || linux::run_needrestart(&ctx))?;
 
         runner.execute(Step::Flatpak, "Flatpak", || linux::run_flatpak(&ctx))?;
+        for prefix in unix::brew_prefixes(&ctx) {
+            let variant = unix::BrewVariant::Prefix(&prefix);
+            runner.execute(Step::BrewFormula, variant.step_title(), || {
+                unix::run_brew_formula(&ctx, variant)
+            })?;
+        }
         runner.execute(Step::BrewFormula, "Brew", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::Path)
         })?;
@@ -240,25 +322,27 @@ fn run() -> Result<()> {
 
     #[cfg(target_os = "macos")]
     {
-        runner.execute(Step::BrewFormula, "Brew (ARM)", || {
-            unix::run_brew_formula(&ctx, unix::BrewVariant::MacArm)
-        })?;
-        runner.execute(Step::BrewFormula, "Brew (Intel)", || {
-            unix::run_brew_formula(&ctx, unix::BrewVariant::MacIntel)
-        })?;
+        let brew_prefixes = unix::brew_prefixes(&ctx);
+        for prefix in &brew_prefixes {
+            let variant = unix::BrewVariant::Prefix(prefix);
+            runner.execute(Step::BrewFormula, variant.step_title(), || {
+                unix::run_brew_formula(&ctx, variant)
+            })?;
+        }
         runner.execute(Step::BrewFormula, "Brew", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::Path)
         })?;
-        runner.execute(Step::BrewCask, "Brew Cask (ARM)", || {
-            unix::run_brew_cask(&ctx, unix::BrewVariant::MacArm)
-        })?;
-        runner.execute(Step::BrewCask, "Brew Cask (Intel)", || {
-            unix::run_brew_cask(&ctx, unix::BrewVariant::MacIntel)
-        })?;
+        for prefix in &brew_prefixes {
+            let variant = unix::BrewVariant::Prefix(prefix);
+            runner.execute(Step::BrewCask, format!("{} - Cask", variant.step_title()), || {
+                unix::run_brew_cask(&ctx, variant)
+            })?;
+        }
         runner.execute(Step::BrewCask, "Brew Cask", || {
             unix::run_brew_cask(&ctx, unix::BrewVariant::Path)
         })?;
         runner.execute(Step::Macports, "MacPorts", || macos::run_macports(&ctx))?;
+        runner.execute(Step::Xcode, "Xcode", || macos::update_xcode(&ctx))?;
         runner.execute(Step::Xcodes, "Xcodes", || macos::update_xcodes(&ctx))?;
         runner.execute(Step::Sparkle, "Sparkle", || macos::run_sparkle(&ctx))?;
         runner.execute(Step::Mas, "App Store", || macos::run_mas(&ctx))?;
@@ -276,14 +360,44 @@ fn run() -> Result<()> {
     #[cfg(target_os = "freebsd")]
     {
         runner.execute(Step::Pkg, "FreeBSD Packages", || freebsd::upgrade_packages(&ctx))?;
+        if config.should_run(Step::Pkg) && config.freebsd_update_jails() {
+            match freebsd::running_jails() {
+                Ok(jails) => {
+                    for jail in jails {
+                        runner.execute(Step::Pkg, format!("FreeBSD Packages ({jail})"), || {
+                            freebsd::upgrade_jail_packages(&ctx, &jail)
+                        })?;
+                    }
+                }
+                Err(e) => runner.execute(Step::Pkg, "FreeBSD Packages (jails)", || {
+                    Err(color_eyre::eyre::eyre!("Could not list the running jails: {e:#}"))
+                })?,
+            }
+        }
         runner.execute(Step::System, "FreeBSD Upgrade", || freebsd::upgrade_freebsd(&ctx))?;
         runner.execute(Step::Audit, "FreeBSD Audit", || freebsd::audit_packages(&ctx))?;
     }
 
+    #[cfg(target_os = "haiku")]
+    {
+        runner.execute(Step::System, "Haiku Packages", || haiku::upgrade_haiku(&ctx))?;
+    }
+
+    #[cfg(target_os = "illumos")]
+    {
+        runner.execute(Step::System, "IPS Packages", || illumos::upgrade_packages(&ctx))?;
+    }
+
+    #[cfg(target_os = "netbsd")]
+    {
+        runner.execute(Step::Pkg, "NetBSD Packages", || netbsd::upgrade_packages(&ctx))?;
+    }
+
     #[cfg(target_os = "openbsd")]
     {
+        runner.execute(Step::System, "OpenBSD Patches", || openbsd::run_syspatch(&ctx))?;
+        runner.execute(Step::Firmware, "OpenBSD Firmware", || openbsd::run_fw_update(&ctx))?;
         runner.execute(Step::Pkg, "OpenBSD Packages", || openbsd::upgrade_packages(&ctx))?;
-        runner.execute(Step::System, "OpenBSD Upgrade", || openbsd::upgrade_openbsd(&ctx))?;
     }
 
     #[cfg(target_os = "android")]
@@ -300,6 +414,8 @@ fn run() -> Result<()> {
         runner.execute(Step::HomeManager, "home-manager", || unix::run_home_manager(&ctx))?;
         runner.execute(Step::Asdf, "asdf", || unix::run_asdf(&ctx))?;
         runner.execute(Step::Mise, "mise", || unix::run_mise(&ctx))?;
+        // pkgin is the package manager of NetBSD, which has its own step
+        #[cfg(not(target_os = "netbsd"))]
         runner.execute(Step::Pkgin, "pkgin", || unix::run_pkgin(&ctx))?;
         runner.execute(Step::Bun, "bun", || unix::run_bun(&ctx))?;
         runner.execute(Step::BunPackages, "bun-packages", || unix::run_bun_packages(&ctx))?;
@@ -417,6 +533,18 @@ fn run() -> Result<()> {
         runner.execute(Step::Powershell, "Powershell Modules Update", || {
             powershell.update_modules(&ctx)
         })?;
+
+        for command in config.powershell_custom_commands() {
+            runner.execute(Step::Powershell, format!("Powershell ({command})"), || {
+                powershell.run_custom_command(&ctx, command)
+            })?;
+        }
+
+        if config.powershell_update_help() {
+            runner.execute(Step::Powershell, "Powershell Help Update", || {
+                powershell.update_help(&ctx)
+            })?;
+        }
     }
 
     if let Some(commands) = config.commands() {
@@ -447,11 +575,25 @@ fn run() -> Result<()> {
             print_result(key, result);
         }
 
+        for note in ctx.take_summary_notes() {
+            print_summary_note(&note);
+        }
+
+        #[cfg(windows)]
+        windows::show_pending_reboot(&ctx);
+
         #[cfg(target_os = "linux")]
         {
-            if let Ok(distribution) = &distribution {
+            if let Ok(distribution) = distribution {
                 distribution.show_summary();
             }
+            linux::show_new_failed_units(&ctx);
+        }
+    }
+
+    if let Some(path) = config.report_json() {
+        if let Err(e) = runner.report().write_json(&path) {
+            print_warning(format!("Failed to write the report to {}: {e}", path.display()));
         }
     }
 
@@ -464,6 +606,9 @@ fn run() -> Result<()> {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    linux::transactional_update_reboot(&ctx)?;
+
     if config.keep_at_end() {
         print_info("\n(R)eboot\n(S)hell\n(Q)uit");
         loop {
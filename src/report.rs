@@ -1,5 +1,12 @@
 use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
 
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StepResult {
     Success,
     Failure,
@@ -42,4 +49,102 @@ impl<'a> Report<'a> {
     pub fn data(&self) -> &ReportData<'a> {
         &self.data
     }
+
+    /// Write the results to `path` as JSON, for `--report-json`.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let steps: Vec<_> = self
+            .data
+            .iter()
+            .map(|(key, result)| JsonStepResult { key, result })
+            .collect();
+        fs::write(path, serde_json::to_string(&steps)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonStepResult<'a> {
+    key: &'a str,
+    result: &'a StepResult,
+}
+
+/// A step result read from the JSON written by `Report::write_json`.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+pub struct ReportedStep {
+    pub key: String,
+    pub result: StepResult,
+}
+
+/// A note that a step leaves for the summary, printed after the step results.
+#[derive(Debug)]
+pub enum SummaryNote {
+    /// Information, such as the packages that were left outdated
+    Info(String),
+    /// Something to act on, such as a reboot
+    Warning(String),
+    /// The step results of a remote host
+    RemoteSteps { host: String, steps: Vec<ReportedStep> },
+}
+
+/// Parse the step results written by `Report::write_json`.
+pub fn parse_json(json: &str) -> Result<Vec<ReportedStep>> {
+    Ok(serde_json::from_str(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let json = r#"[{"key":"System update","result":"success"},{"key":"Cargo","result":"failure"},{"key":"pipx","result":"ignored"},{"key":"snap","result":{"skipped":"snap is not installed"}}]"#;
+        assert_eq!(
+            parse_json(json).unwrap(),
+            [
+                ReportedStep {
+                    key: String::from("System update"),
+                    result: StepResult::Success,
+                },
+                ReportedStep {
+                    key: String::from("Cargo"),
+                    result: StepResult::Failure,
+                },
+                ReportedStep {
+                    key: String::from("pipx"),
+                    result: StepResult::Ignored,
+                },
+                ReportedStep {
+                    key: String::from("snap"),
+                    result: StepResult::Skipped(String::from("snap is not installed")),
+                },
+            ]
+        );
+        assert!(parse_json("").is_err());
+    }
+
+    #[test]
+    fn test_write_json_round_trip() {
+        let mut report = Report::new();
+        report.push_result(Some(("System update", StepResult::Success)));
+        report.push_result(Some(("Cargo", StepResult::Failure)));
+        report.push_result(Some(("pipx", StepResult::Ignored)));
+        report.push_result(Some((
+            "snap",
+            StepResult::Skipped(String::from("snap is not installed")),
+        )));
+
+        let path = std::env::temp_dir().join(format!("topgrade-report-test-{}.json", std::process::id()));
+        report.write_json(&path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let steps = parse_json(&json).unwrap();
+        let steps: Vec<_> = steps.iter().map(|step| (step.key.as_str(), &step.result)).collect();
+        let expected: Vec<_> = report
+            .data()
+            .iter()
+            .map(|(key, result)| (key.as_ref(), result))
+            .collect();
+        assert_eq!(steps, expected);
+    }
 }
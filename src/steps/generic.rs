@@ -36,6 +36,22 @@ pub fn is_wsl() -> Result<bool> {
     Ok(false)
 }
 
+/// Whether we are running inside Crostini, the Linux container of ChromeOS.
+#[cfg(target_os = "linux")]
+pub fn is_crostini() -> bool {
+    let crostini = detect_crostini(
+        Path::new("/dev/.cros_milestone"),
+        env::var_os("CROS_USER_ID_HASH").is_some(),
+    );
+    debug!("Crostini: {}", crostini);
+    crostini
+}
+
+#[cfg(target_os = "linux")]
+fn detect_crostini(cros_milestone: &Path, has_cros_user_id_hash: bool) -> bool {
+    cros_milestone.exists() || has_cros_user_id_hash
+}
+
 pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     let cargo_dir = env::var_os("CARGO_HOME")
         .map(PathBuf::from)
@@ -974,3 +990,20 @@ pub fn run_platform_io(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.run_type().execute(bin_path).arg("upgrade").status_checked()
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_crostini() {
+        let dir = tempfile::tempdir().unwrap();
+        let cros_milestone = dir.path().join(".cros_milestone");
+
+        assert!(!detect_crostini(&cros_milestone, false));
+        assert!(detect_crostini(&cros_milestone, true));
+
+        fs::write(&cros_milestone, "120").unwrap();
+        assert!(detect_crostini(&cros_milestone, false));
+    }
+}
@@ -57,7 +57,7 @@ pub fn run_git_pull(ctx: &ExecutionContext) -> Result<()> {
                 repos.insert_if_repo(HOME_DIR.join(".dotfiles"));
             }
 
-            let powershell = crate::steps::powershell::Powershell::new();
+            let powershell = crate::steps::powershell::Powershell::new(config);
             if let Some(profile) = powershell.profile() {
                 repos.insert_if_repo(profile);
             }
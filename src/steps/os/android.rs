@@ -1,3 +1,5 @@
+use std::env;
+
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
 use crate::terminal::print_separator;
@@ -6,29 +8,53 @@ use crate::utils::which;
 use crate::Step;
 use color_eyre::eyre::Result;
 
+/// Packages that make up the Termux bootstrap. They are upgraded before everything else so
+/// that a new keyring or a mirror list written by `termux-change-repo` is in place when the
+/// remaining packages are fetched.
+const BOOTSTRAP_PACKAGES: [&str; 3] = ["termux-keyring", "termux-tools", "apt"];
+
+/// Whether `prefix` is the Termux installation prefix (`/data/data/com.termux/files/usr`).
+fn is_termux_prefix(prefix: &str) -> bool {
+    prefix.trim_end_matches('/').ends_with("/com.termux/files/usr")
+}
+
+/// Detect Termux through `$PREFIX`, which also works for Linux builds running inside Termux,
+/// where there is no `/etc/os-release` to identify the distribution.
+pub fn is_termux() -> bool {
+    env::var("PREFIX").is_ok_and(|prefix| is_termux_prefix(&prefix))
+}
+
 pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
-    //let pkg = require("pkg")?;
-    let pkg = which("nala").or_else(|| which("pkg")).unwrap();
+    let pkg = which("nala").map_or_else(|| require("pkg"), Ok)?;
 
     print_separator("Termux Packages");
 
     let is_nala = pkg.ends_with("nala");
+    let yes = ctx.config().yes(Step::System);
+
+    if !is_nala {
+        let mut command = ctx.run_type().execute(&pkg);
+        command.args(["install", "--only-upgrade"]).args(BOOTSTRAP_PACKAGES);
+        if yes {
+            command.args(["-y", "-o", "Dpkg::Options::=--force-confdef"]);
+        }
+        command.status_checked()?;
+    }
 
     let mut command = ctx.run_type().execute(&pkg);
     command.arg("upgrade");
-
-    if ctx.config().yes(Step::System) {
-        command.arg("-y");
+    if yes {
+        command.args(["-y", "-o", "Dpkg::Options::=--force-confdef"]);
     }
     command.status_checked()?;
 
     if !is_nala && ctx.config().cleanup() {
-        ctx.run_type().execute(&pkg).arg("clean").status_checked()?;
+        ctx.run_type().execute(&pkg).arg("autoclean").status_checked()?;
 
         let apt = require("apt")?;
         let mut command = ctx.run_type().execute(apt);
         command.arg("autoremove");
-        if ctx.config().yes(Step::System) {
+        if yes {
             command.arg("-y");
         }
         command.status_checked()?;
@@ -36,3 +62,16 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_termux_prefix() {
+        assert!(is_termux_prefix("/data/data/com.termux/files/usr"));
+        assert!(is_termux_prefix("/data/data/com.termux/files/usr/"));
+        assert!(!is_termux_prefix("/usr"));
+        assert!(!is_termux_prefix("/data/data/com.termux/files/home"));
+    }
+}
@@ -1,17 +1,25 @@
 use std::env::var_os;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
+use chrono::{DateTime, FixedOffset, Utc};
 use color_eyre::eyre;
 use color_eyre::eyre::Result;
+use etcetera::base_strategy::BaseStrategy;
+use regex::Regex;
 use walkdir::WalkDir;
 
 use crate::command::CommandExt;
-use crate::error::TopgradeError;
+use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
 use crate::sudo::Sudo;
-use crate::utils::which;
-use crate::{config, Step};
+use crate::terminal::print_separator;
+use crate::utils::{require_option, which, REQUIRE_SUDO};
+use crate::{config, Step, XDG_DIRS};
+
+const MIRRORLIST_PATH: &str = "/etc/pacman.d/mirrorlist";
+const ARCH_NEWS_FEED: &str = "https://archlinux.org/feeds/news/";
 
 fn get_execution_path() -> OsString {
     let mut path = OsString::from("/usr/bin:");
@@ -53,7 +61,12 @@ impl ArchPackageManager for YayParu {
 
         if ctx.config().cleanup() {
             let mut command = ctx.run_type().execute(&self.executable);
-            command.arg("--pacman").arg(&self.pacman).arg("-Scc");
+            if paccache_cleanup(ctx)? {
+                // The pacman cache was already trimmed, only clean the AUR helper's own cache
+                command.arg("-Sc").arg("--aur");
+            } else {
+                command.arg("--pacman").arg(&self.pacman).arg("-Scc");
+            }
             if ctx.config().yes(Step::System) {
                 command.arg("--noconfirm");
             }
@@ -125,6 +138,10 @@ impl ArchPackageManager for Trizen {
         if ctx.config().cleanup() {
             let mut command = ctx.run_type().execute(&self.executable);
             command.arg("-Sc");
+            if paccache_cleanup(ctx)? {
+                // The pacman cache was already trimmed, only clean the AUR helper's own cache
+                command.arg("--aur");
+            }
             if ctx.config().yes(Step::System) {
                 command.arg("--noconfirm");
             }
@@ -160,7 +177,7 @@ impl ArchPackageManager for Pacman {
         }
         command.status_checked()?;
 
-        if ctx.config().cleanup() {
+        if ctx.config().cleanup() && !paccache_cleanup(ctx)? {
             let mut command = ctx.run_type().execute(&self.sudo);
             command.arg(&self.executable).arg("-Scc");
             if ctx.config().yes(Step::System) {
@@ -212,6 +229,10 @@ impl ArchPackageManager for Pikaur {
         if ctx.config().cleanup() {
             let mut command = ctx.run_type().execute(&self.executable);
             command.arg("-Sc");
+            if paccache_cleanup(ctx)? {
+                // The pacman cache was already trimmed, only clean the AUR helper's own cache
+                command.arg("--aur");
+            }
             if ctx.config().yes(Step::System) {
                 command.arg("--noconfirm");
             }
@@ -308,38 +329,215 @@ impl ArchPackageManager for Aura {
     }
 }
 
+/// Trims the package cache with paccache, keeping the number of versions set by `paccache_keep`.
+///
+/// Returns whether the cache was cleaned, which requires `paccache_keep` and pacman-contrib.
+fn paccache_cleanup(ctx: &ExecutionContext) -> Result<bool> {
+    let Some(keep) = ctx.config().paccache_keep() else {
+        return Ok(false);
+    };
+    let Some(paccache) = which("paccache") else {
+        return Ok(false);
+    };
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&paccache)
+        .arg(format!("-rk{keep}"))
+        .status_checked()?;
+
+    // Drop every cached version of the packages that are not installed anymore
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&paccache)
+        .arg("-ruk0")
+        .status_checked()?;
+
+    Ok(true)
+}
+
 fn box_package_manager<P: 'static + ArchPackageManager>(package_manager: P) -> Box<dyn ArchPackageManager> {
     Box::new(package_manager) as Box<dyn ArchPackageManager>
 }
 
-pub fn get_arch_package_manager(ctx: &ExecutionContext) -> Option<Box<dyn ArchPackageManager>> {
+/// The package managers tried in order when `arch_package_manager` is `autodetect`
+const AUTODETECT_ORDER: [config::ArchPackageManager; 8] = [
+    config::ArchPackageManager::GarudaUpdate,
+    config::ArchPackageManager::Paru,
+    config::ArchPackageManager::Yay,
+    config::ArchPackageManager::Trizen,
+    config::ArchPackageManager::Pikaur,
+    config::ArchPackageManager::Pamac,
+    config::ArchPackageManager::Pacman,
+    config::ArchPackageManager::Aura,
+];
+
+/// Picks the package manager to use, `get` returns it when it is available.
+///
+/// A package manager set in the configuration never falls back to another one.
+fn select_arch_package_manager<T>(
+    choice: config::ArchPackageManager,
+    get: impl Fn(config::ArchPackageManager) -> Option<T>,
+) -> Result<T> {
+    match choice {
+        config::ArchPackageManager::Autodetect => AUTODETECT_ORDER
+            .into_iter()
+            .find_map(get)
+            .ok_or_else(|| TopgradeError::FailedGettingPackageManager.into()),
+        forced => {
+            get(forced).ok_or_else(|| TopgradeError::MissingArchPackageManager(forced.as_ref().to_string()).into())
+        }
+    }
+}
+
+pub fn get_arch_package_manager(ctx: &ExecutionContext) -> Result<Box<dyn ArchPackageManager>> {
     let pacman = which("powerpill").unwrap_or_else(|| PathBuf::from("pacman"));
 
-    match ctx.config().arch_package_manager() {
-        config::ArchPackageManager::Autodetect => GarudaUpdate::get()
-            .map(box_package_manager)
-            .or_else(|| YayParu::get("paru", &pacman).map(box_package_manager))
-            .or_else(|| YayParu::get("yay", &pacman).map(box_package_manager))
-            .or_else(|| Trizen::get().map(box_package_manager))
-            .or_else(|| Pikaur::get().map(box_package_manager))
-            .or_else(|| Pamac::get().map(box_package_manager))
-            .or_else(|| Pacman::get(ctx).map(box_package_manager))
-            .or_else(|| Aura::get(ctx).map(box_package_manager)),
-        config::ArchPackageManager::GarudaUpdate => GarudaUpdate::get().map(box_package_manager),
-        config::ArchPackageManager::Trizen => Trizen::get().map(box_package_manager),
-        config::ArchPackageManager::Paru => YayParu::get("paru", &pacman).map(box_package_manager),
-        config::ArchPackageManager::Yay => YayParu::get("yay", &pacman).map(box_package_manager),
-        config::ArchPackageManager::Pacman => Pacman::get(ctx).map(box_package_manager),
-        config::ArchPackageManager::Pikaur => Pikaur::get().map(box_package_manager),
-        config::ArchPackageManager::Pamac => Pamac::get().map(box_package_manager),
-        config::ArchPackageManager::Aura => Aura::get(ctx).map(box_package_manager),
-    }
+    select_arch_package_manager(
+        ctx.config().arch_package_manager(),
+        |package_manager| match package_manager {
+            config::ArchPackageManager::Autodetect => None,
+            config::ArchPackageManager::GarudaUpdate => GarudaUpdate::get().map(box_package_manager),
+            config::ArchPackageManager::Trizen => Trizen::get().map(box_package_manager),
+            config::ArchPackageManager::Paru => YayParu::get("paru", &pacman).map(box_package_manager),
+            config::ArchPackageManager::Yay => YayParu::get("yay", &pacman).map(box_package_manager),
+            config::ArchPackageManager::Pacman => Pacman::get(ctx).map(box_package_manager),
+            config::ArchPackageManager::Pikaur => Pikaur::get().map(box_package_manager),
+            config::ArchPackageManager::Pamac => Pamac::get().map(box_package_manager),
+            config::ArchPackageManager::Aura => Aura::get(ctx).map(box_package_manager),
+        },
+    )
 }
 
 pub fn upgrade_arch_linux(ctx: &ExecutionContext) -> Result<()> {
-    let package_manager =
-        get_arch_package_manager(ctx).ok_or_else(|| eyre::Report::from(TopgradeError::FailedGettingPackageManager))?;
-    package_manager.upgrade(ctx)
+    let package_manager = get_arch_package_manager(ctx)?;
+
+    let news_file = arch_news_file();
+    if ctx.config().arch_news_check() && !ctx.config().ignore_news() {
+        check_arch_news(&news_file)?;
+    }
+
+    package_manager.upgrade(ctx)?;
+
+    // Done here rather than by each package manager, so the files database is synced only once
+    if ctx.config().pacman_files_db() {
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        ctx.run_type().execute(sudo).args(["pacman", "-Fy"]).status_checked()?;
+    }
+
+    if ctx.config().arch_news_check() && !ctx.run_type().dry() {
+        if let Some(parent) = news_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&news_file, Utc::now().to_rfc3339())?;
+    }
+
+    Ok(())
+}
+
+/// The file holding the time of the last successful upgrade, news published before it are considered read.
+fn arch_news_file() -> PathBuf {
+    XDG_DIRS
+        .state_dir()
+        .unwrap_or_else(|| XDG_DIRS.cache_dir())
+        .join("topgrade_arch_news")
+}
+
+/// Fails when Arch Linux news were published since the last successful upgrade.
+fn check_arch_news(news_file: &Path) -> Result<()> {
+    // informant keeps track of the read news by itself, and exits with the number of unread items
+    if let Some(informant) = which("informant") {
+        let output = Command::new(informant).arg("check").output_checked_with(|_| Ok(()))?;
+        if !output.status.success() {
+            return Err(eyre::eyre!(
+                "There are unread Arch Linux news, read them with `informant read` or pass --ignore-news"
+            ));
+        }
+        return Ok(());
+    }
+
+    let Some(last_upgrade) = std::fs::read_to_string(news_file)
+        .ok()
+        .and_then(|content| DateTime::parse_from_rfc3339(content.trim()).ok())
+    else {
+        // Nothing to compare against before the first successful upgrade
+        return Ok(());
+    };
+
+    let feed = Command::new("curl")
+        .args(["-fsSL", ARCH_NEWS_FEED])
+        .output_checked_utf8()?
+        .stdout;
+
+    let unread: Vec<String> = parse_arch_news(&feed)
+        .into_iter()
+        .filter(|(published, _)| *published > last_upgrade)
+        .map(|(_, title)| title)
+        .collect();
+
+    if unread.is_empty() {
+        return Ok(());
+    }
+
+    Err(eyre::eyre!(
+        "There are unread Arch Linux news, read them at https://archlinux.org/news/ or pass --ignore-news:\n{}",
+        unread.join("\n")
+    ))
+}
+
+/// Returns the publication date and title of the items in the Arch Linux news RSS feed.
+fn parse_arch_news(feed: &str) -> Vec<(DateTime<FixedOffset>, String)> {
+    let item = Regex::new(r"(?s)<item>(.*?)</item>").unwrap();
+    let title = Regex::new(r"(?s)<title>(.*?)</title>").unwrap();
+    let pub_date = Regex::new(r"(?s)<pubDate>(.*?)</pubDate>").unwrap();
+
+    item.captures_iter(feed)
+        .filter_map(|captures| {
+            let item = captures.get(1)?.as_str();
+            let title = title.captures(item)?.get(1)?.as_str();
+            let published = DateTime::parse_from_rfc2822(pub_date.captures(item)?.get(1)?.as_str().trim()).ok()?;
+            Some((published, unescape_xml(title.trim())))
+        })
+        .collect()
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+pub fn refresh_mirrors(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().refresh_mirrors() {
+        return Err(SkipStep(String::from("Refreshing the mirrorlist is not enabled")).into());
+    }
+
+    let rate_mirrors = which("rate-mirrors");
+    let reflector = which("reflector");
+    if rate_mirrors.is_none() && reflector.is_none() {
+        return Err(SkipStep(String::from("Neither rate-mirrors nor reflector is installed")).into());
+    }
+
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    print_separator("Mirrors");
+
+    let mut command = ctx.run_type().execute(sudo);
+    if let Some(rate_mirrors) = rate_mirrors {
+        command
+            .arg(rate_mirrors)
+            .args(["--allow-root", "--save", MIRRORLIST_PATH, "arch"]);
+    } else if let Some(reflector) = reflector {
+        command
+            .arg(reflector)
+            .args(["--save", MIRRORLIST_PATH])
+            .args(ctx.config().reflector_arguments().split_whitespace());
+    }
+
+    command.status_checked()
 }
 
 pub fn show_pacnew() {
@@ -362,3 +560,68 @@ pub fn show_pacnew() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(
+        package_managers: &[config::ArchPackageManager],
+    ) -> impl Fn(config::ArchPackageManager) -> Option<config::ArchPackageManager> + '_ {
+        |package_manager| package_managers.contains(&package_manager).then_some(package_manager)
+    }
+
+    #[test]
+    fn test_autodetect_arch_package_manager() {
+        use config::ArchPackageManager::*;
+
+        assert_eq!(
+            select_arch_package_manager(Autodetect, installed(&[Pacman, Yay, Paru])).unwrap(),
+            Paru
+        );
+        assert_eq!(
+            select_arch_package_manager(Autodetect, installed(&[Pacman, Yay])).unwrap(),
+            Yay
+        );
+        assert_eq!(
+            select_arch_package_manager(Autodetect, installed(&[Pacman])).unwrap(),
+            Pacman
+        );
+        assert!(select_arch_package_manager(Autodetect, installed(&[])).is_err());
+    }
+
+    #[test]
+    fn test_forced_arch_package_manager() {
+        use config::ArchPackageManager::*;
+
+        assert_eq!(
+            select_arch_package_manager(Yay, installed(&[Pacman, Yay, Paru])).unwrap(),
+            Yay
+        );
+
+        let error = select_arch_package_manager(Yay, installed(&[Pacman, Paru])).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TopgradeError>(),
+            Some(&TopgradeError::MissingArchPackageManager(String::from("yay")))
+        );
+    }
+
+    #[test]
+    fn test_parse_arch_news() {
+        let news = parse_arch_news(include_str!("arch_news.xml"));
+
+        assert_eq!(news.len(), 2);
+        assert_eq!(
+            news[0].1,
+            "Manual intervention for pacman 7.0.0 and local repositories required"
+        );
+        assert_eq!(
+            news[0].0,
+            DateTime::parse_from_rfc3339("2024-09-14T11:43:22+00:00").unwrap()
+        );
+        assert_eq!(
+            news[1].1,
+            "The sshd service needs to be restarted after upgrading to openssh-9.8p1"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
-use crate::terminal::print_separator;
+use crate::terminal::{print_separator, print_warning};
 use crate::utils::{require_option, REQUIRE_SUDO};
 use crate::Step;
 use color_eyre::eyre::Result;
@@ -8,13 +8,82 @@ use std::process::Command;
 
 pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let yes = ctx.config().yes(Step::System);
+
     print_separator("DragonFly BSD Packages");
-    let mut cmd = ctx.run_type().execute(sudo);
-    cmd.args(["/usr/local/sbin/pkg", "upgrade"]);
-    if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+
+    check_pkg_abi();
+
+    let pkg_command = |args: &[&str]| {
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg("/usr/local/sbin/pkg").args(args);
+        if yes {
+            command.arg("-y");
+        }
+        command
+    };
+
+    ctx.run_type()
+        .execute(sudo)
+        .args(["/usr/local/sbin/pkg", "update"])
+        .status_checked()?;
+    pkg_command(&["upgrade"]).status_checked()?;
+
+    if ctx.config().cleanup() {
+        pkg_command(&["clean", "-a"]).status_checked()?;
+        pkg_command(&["autoremove"]).status_checked()?;
+    }
+
+    Ok(())
+}
+
+/// Warn when the system is older than the one the packages are built for, as `pkg` then installs
+/// packages that may not run on it.
+fn check_pkg_abi() {
+    let Ok(release) = Command::new("uname").arg("-r").output_checked_utf8() else {
+        return;
+    };
+    let Ok(abi) = Command::new("/usr/local/sbin/pkg")
+        .args(["config", "ABI"])
+        .output_checked_utf8()
+    else {
+        return;
+    };
+    let release = release.stdout.trim();
+
+    let (Some(system_version), Some(abi_version)) = (release_version(release), abi_version(abi.stdout.trim())) else {
+        return;
+    };
+    if system_version >= abi_version {
+        return;
     }
-    cmd.status_checked()
+
+    let upgrade = if release.ends_with("-RELEASE") {
+        "upgrade to the latest release"
+    } else {
+        "rebuild the system from master"
+    };
+    print_warning(format!(
+        "This system runs DragonFly {release}, but the packages are built for DragonFly {}.{}, {upgrade} before upgrading the packages",
+        abi_version.0, abi_version.1
+    ));
+}
+
+/// Parse the version from the output of `uname -r`, such as `6.4-RELEASE` for a release or
+/// `6.5-DEVELOPMENT` for master.
+fn release_version(release: &str) -> Option<(u32, u32)> {
+    let version = release.split_once('-').map_or(release, |(version, _)| version);
+    parse_version(version)
+}
+
+/// Parse the version from the pkg ABI, such as `dragonfly:6.4:x86:64`.
+fn abi_version(abi: &str) -> Option<(u32, u32)> {
+    parse_version(abi.split(':').nth(1)?)
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
 }
 
 pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
@@ -32,3 +101,21 @@ pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_version() {
+        assert_eq!(release_version("6.4-RELEASE"), Some((6, 4)));
+        assert_eq!(release_version("6.5-DEVELOPMENT"), Some((6, 5)));
+        assert_eq!(release_version("garbage"), None);
+    }
+
+    #[test]
+    fn test_abi_version() {
+        assert_eq!(abi_version("dragonfly:6.4:x86:64"), Some((6, 4)));
+        assert_eq!(abi_version("dragonfly"), None);
+    }
+}
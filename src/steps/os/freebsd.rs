@@ -1,4 +1,5 @@
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 use crate::terminal::print_separator;
 use crate::utils::{require_option, REQUIRE_SUDO};
@@ -8,6 +9,13 @@ use std::process::Command;
 
 pub fn upgrade_freebsd(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    let version = Command::new("freebsd-version").arg("-u").output_checked_utf8()?;
+    let version = version.stdout.trim();
+    if !freebsd_update_supported(version) {
+        return Err(SkipStep(format!("freebsd-update only supports releases, not {version}")).into());
+    }
+
     print_separator("FreeBSD Update");
     ctx.run_type()
         .execute(sudo)
@@ -28,6 +36,40 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
+/// Returns the names of the running jails.
+pub fn running_jails() -> Result<Vec<String>> {
+    let output = Command::new("jls").arg("name").output_checked_utf8()?;
+    Ok(output
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+pub fn upgrade_jail_packages(ctx: &ExecutionContext, jail: &str) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    print_separator(format!("FreeBSD Packages ({jail})"));
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.args(["/usr/sbin/pkg", "-j", jail, "upgrade"]);
+    if ctx.config().yes(Step::Pkg) {
+        command.arg("-y");
+    }
+    command.status_checked()
+}
+
+/// Whether `freebsd-update` can update the system running `version`, as printed by `freebsd-version`.
+///
+/// Only the releases and their betas and release candidates are supported, not the -CURRENT and -STABLE branches.
+fn freebsd_update_supported(version: &str) -> bool {
+    let branch = version.split_once('-').map(|(_, branch)| branch).unwrap_or_default();
+    ["RELEASE", "BETA", "RC"]
+        .iter()
+        .any(|supported| branch.starts_with(supported))
+}
+
 pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
 
@@ -38,3 +80,19 @@ pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freebsd_update_supported() {
+        assert!(freebsd_update_supported("14.0-RELEASE"));
+        assert!(freebsd_update_supported("13.2-RELEASE-p9"));
+        assert!(freebsd_update_supported("14.1-BETA2"));
+        assert!(freebsd_update_supported("14.1-RC1-p1"));
+        assert!(!freebsd_update_supported("15.0-CURRENT"));
+        assert!(!freebsd_update_supported("14.0-STABLE"));
+        assert!(!freebsd_update_supported(""));
+    }
+}
@@ -0,0 +1,23 @@
+use crate::command::CommandExt;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::print_separator;
+use crate::utils::require;
+use crate::Step;
+use color_eyre::eyre::Result;
+
+pub fn upgrade_haiku(ctx: &ExecutionContext) -> Result<()> {
+    let pkgman = require("pkgman")?;
+
+    print_separator("Haiku Packages");
+
+    ctx.run_type().execute(&pkgman).arg("refresh").status_checked()?;
+
+    // pkgman has no cleanup of the old package states, so they are left alone even with `cleanup`:
+    // they are the states the boot loader offers to roll back to, and removing them is up to the user
+    let mut command = ctx.run_type().execute(&pkgman);
+    command.arg("update");
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+    command.status_checked()
+}
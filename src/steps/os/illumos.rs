@@ -0,0 +1,80 @@
+use std::process::Command;
+
+use crate::command::CommandExt;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::{print_separator, print_warning};
+use crate::utils::{require, which};
+use color_eyre::eyre::Result;
+use tracing::debug;
+
+pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
+    let pfexec = require("pfexec")?;
+    let pkg = require("pkg")?;
+
+    print_separator("IPS Packages");
+
+    ctx.run_type()
+        .execute(&pfexec)
+        .arg(&pkg)
+        .arg("refresh")
+        .status_checked()?;
+
+    let mut command = ctx.run_type().execute(&pfexec);
+    command.arg(&pkg).args(["update", "-v"]);
+    if let Some(args) = ctx.config().illumos_pkg_arguments() {
+        command.args(args.split_whitespace());
+    }
+    // `pkg update` exits with 4 when there is nothing to update
+    command.status_checked_with_codes(&[4])?;
+
+    if ctx.run_type().dry() {
+        return Ok(());
+    }
+
+    // Finding the new boot environment is only informative, the update is already installed
+    if let Some(beadm) = which("beadm") {
+        match Command::new(beadm).args(["list", "-H"]).output_checked_utf8() {
+            Ok(output) => {
+                if let Some(name) = pending_boot_environment(&output.stdout) {
+                    print_warning(format!(
+                        "The update was installed into the boot environment {name}, reboot into it to finish the update"
+                    ));
+                }
+            }
+            Err(e) => debug!("Could not list the boot environments: {e:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the boot environment that will be active on the next boot but isn't active now, from the
+/// output of `beadm list -H`. Its fields are separated by `;`, and the third one holds `N` for the
+/// boot environment that is active now and `R` for the one that is active on reboot.
+fn pending_boot_environment(output: &str) -> Option<&str> {
+    output.lines().find_map(|line| {
+        let mut fields = line.split(';');
+        let name = fields.next()?;
+        let active = fields.nth(1)?;
+        (active.contains('R') && !active.contains('N')).then_some(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_boot_environment() {
+        let output = "openindiana-2024:03:01;5d8a0b5e-6a2e-4e4c-9c0a-1d9bbbd7c3a4;N;/;1.2G;static;1709290000\n\
+                      openindiana-2024:04:12;a3b1c0de-7f6e-4d2c-8b1a-2e0f4c5d6e7f;R;-;10.5G;static;1712900000\n";
+        assert_eq!(pending_boot_environment(output), Some("openindiana-2024:04:12"));
+    }
+
+    #[test]
+    fn test_no_pending_boot_environment() {
+        let output = "openindiana;5d8a0b5e-6a2e-4e4c-9c0a-1d9bbbd7c3a4;NR;/;1.2G;static;1709290000\n\
+                      openindiana-1;a3b1c0de-7f6e-4d2c-8b1a-2e0f4c5d6e7f;-;-;10.5G;static;1712900000\n";
+        assert_eq!(pending_boot_environment(output), None);
+    }
+}
@@ -1,38 +1,69 @@
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{self, Result};
+use console::style;
+use futures::stream::{iter, StreamExt};
 use ini::Ini;
+use regex::Regex;
+use semver::Version;
+use serde::Deserialize;
+use tokio::process::Command as AsyncCommand;
+use tokio::runtime;
 use tracing::{debug, warn};
 
 use crate::command::CommandExt;
+use crate::config::{
+    AptUpgradeMode, Config, FallbackPackageManager, NeedrestartMode, PreUpgradeSnapshot, SuseMode,
+    TransactionalUpdateReboot,
+};
 use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
-use crate::steps::generic::is_wsl;
+use crate::report::SummaryNote;
+use crate::steps::generic::{is_crostini, is_wsl};
 use crate::steps::os::archlinux;
-use crate::terminal::{print_separator, prompt_yesno};
+use crate::sudo::Sudo;
+use crate::terminal::{print_separator, print_warning, prompt_yesno};
 use crate::utils::{require, require_option, which, PathExt, REQUIRE_SUDO};
 use crate::{Step, HOME_DIR};
 
 static OS_RELEASE_PATH: &str = "/etc/os-release";
 
+/// Set when `transactional-update` created a snapshot that is not booted yet, to reboot into it
+static TRANSACTIONAL_UPDATE_PENDING: AtomicBool = AtomicBool::new(false);
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Distribution {
     Alpine,
+    AltLinux,
+    /// Amazon Linux 2023 and later, older releases are handled as `CentOS`
+    AmazonLinux,
     Wolfi,
     Arch,
+    AzureLinux,
     Bedrock,
     CentOS,
     Chimera,
     ClearLinux,
+    Crux,
     Fedora,
     FedoraImmutable,
     Debian,
     Gentoo,
+    Guix,
+    Mageia,
     OpenMandriva,
     OpenSuseTumbleweed,
     PCLinuxOS,
+    Photon,
+    PopOS,
+    Serpent,
     Suse,
     SuseMicro,
     Vanilla,
@@ -41,23 +72,38 @@ pub enum Distribution {
     Exherbo,
     NixOS,
     KDENeon,
+    /// MX Linux and antiX
+    MX,
     Nobara,
 }
 
 impl Distribution {
     fn parse_os_release(os_release: &Ini) -> Result<Self> {
         let section = os_release.general_section();
-        let id = section.get("ID");
+        // Some distributions (e.g. Deepin, openEuler) don't use a lowercase ID
+        let id = section.get("ID").map(str::to_lowercase);
         let name = section.get("NAME");
         let variant = section.get("VARIANT");
+        let version_id = section.get("VERSION_ID");
         let id_like: Option<Vec<&str>> = section.get("ID_LIKE").map(|s| s.split_whitespace().collect());
 
-        Ok(match id {
-            Some("alpine") => Distribution::Alpine,
+        Ok(match id.as_deref() {
+            Some("alpine") | Some("adelie") | Some("postmarketos") => Distribution::Alpine,
+            Some("altlinux") => Distribution::AltLinux,
             Some("chimera") => Distribution::Chimera,
             Some("wolfi") => Distribution::Wolfi,
-            Some("centos") | Some("rhel") | Some("ol") => Distribution::CentOS,
+            Some("centos") | Some("rhel") | Some("ol") | Some("openeuler") => Distribution::CentOS,
+            Some("amzn")
+                if version_id
+                    .and_then(|v| v.split('.').next())
+                    .and_then(|major| major.parse::<u32>().ok())
+                    .map(|major| major >= 2023)
+                    .unwrap_or(false) =>
+            {
+                Distribution::AmazonLinux
+            }
             Some("clear-linux-os") => Distribution::ClearLinux,
+            Some("crux") => Distribution::Crux,
             Some("fedora") => {
                 return if let Some(variant) = variant {
                     match variant {
@@ -73,16 +119,23 @@ impl Distribution {
 
             Some("nobara") => Distribution::Nobara,
             Some("void") => Distribution::Void,
-            Some("debian") | Some("pureos") | Some("Deepin") | Some("linuxmint") => Distribution::Debian,
+            Some("debian") | Some("pureos") | Some("deepin") | Some("linuxmint") => Distribution::Debian,
             Some("arch") | Some("manjaro-arm") | Some("garuda") | Some("artix") => Distribution::Arch,
             Some("solus") => Distribution::Solus,
             Some("gentoo") => Distribution::Gentoo,
             Some("exherbo") => Distribution::Exherbo,
             Some("nixos") => Distribution::NixOS,
+            Some("guix") => Distribution::Guix,
             Some("opensuse-microos") => Distribution::SuseMicro,
             Some("neon") => Distribution::KDENeon,
+            Some("mageia") => Distribution::Mageia,
             Some("openmandriva") => Distribution::OpenMandriva,
             Some("pclinuxos") => Distribution::PCLinuxOS,
+            Some("photon") => Distribution::Photon,
+            Some("pop") => Distribution::PopOS,
+            Some("mx") | Some("antix") => Distribution::MX,
+            Some("serpentos") => Distribution::Serpent,
+            Some("mariner") | Some("azurelinux") => Distribution::AzureLinux,
             _ => {
                 if let Some(name) = name {
                     if name.contains("Vanilla") {
@@ -103,13 +156,21 @@ impl Distribution {
                         };
                     } else if id_like.contains(&"arch") || id_like.contains(&"archlinux") {
                         return Ok(Distribution::Arch);
+                    } else if id_like.contains(&"altlinux") {
+                        return Ok(Distribution::AltLinux);
                     } else if id_like.contains(&"alpine") {
                         return Ok(Distribution::Alpine);
+                    } else if id_like.contains(&"mageia") {
+                        return Ok(Distribution::Mageia);
                     } else if id_like.contains(&"fedora") {
                         return Ok(Distribution::Fedora);
                     }
                 }
-                return Err(TopgradeError::UnknownLinuxDistribution.into());
+                return Err(TopgradeError::UnknownLinuxDistribution {
+                    id: id.unwrap_or_default(),
+                    id_like: section.get("ID_LIKE").unwrap_or_default().to_string(),
+                }
+                .into());
             }
         })
     }
@@ -132,18 +193,46 @@ impl Distribution {
         Err(TopgradeError::EmptyOSReleaseFile.into())
     }
 
+    /// Same as `detect`, but falls back to the distribution using `fallback_package_manager` when it is set.
+    pub fn detect_with_fallback(config: &Config) -> Result<Self> {
+        Self::detect().or_else(|e| match config.fallback_package_manager() {
+            Some(package_manager) => {
+                print_warning(format!(
+                    "{e}, upgrading with the fallback package manager {}",
+                    package_manager.as_ref()
+                ));
+                Ok(Self::from_package_manager(package_manager))
+            }
+            None => Err(e),
+        })
+    }
+
+    fn from_package_manager(package_manager: FallbackPackageManager) -> Self {
+        match package_manager {
+            FallbackPackageManager::Apt => Distribution::Debian,
+            FallbackPackageManager::Dnf => Distribution::Fedora,
+            FallbackPackageManager::Pacman => Distribution::Arch,
+            FallbackPackageManager::Apk => Distribution::Alpine,
+            FallbackPackageManager::Zypper => Distribution::Suse,
+            FallbackPackageManager::Xbps => Distribution::Void,
+        }
+    }
+
     pub fn upgrade(self, ctx: &ExecutionContext) -> Result<()> {
         print_separator("System update");
 
         match self {
-            Distribution::Alpine => upgrade_alpine_linux(ctx),
-            Distribution::Chimera => upgrade_chimera_linux(ctx),
-            Distribution::Wolfi => upgrade_wolfi_linux(ctx),
+            Distribution::Alpine | Distribution::Chimera | Distribution::Wolfi => upgrade_apk_based(ctx),
+            Distribution::AltLinux => upgrade_alt_linux(ctx),
             Distribution::Arch => archlinux::upgrade_arch_linux(ctx),
             Distribution::CentOS | Distribution::Fedora => upgrade_redhat(ctx),
+            Distribution::AmazonLinux => upgrade_amazon_linux(ctx),
             Distribution::FedoraImmutable => upgrade_fedora_immutable(ctx),
             Distribution::ClearLinux => upgrade_clearlinux(ctx),
+            Distribution::Crux => upgrade_crux(ctx),
             Distribution::Debian => upgrade_debian(ctx),
+            Distribution::PopOS => upgrade_pop_os(ctx),
+            Distribution::MX => upgrade_mx(ctx),
             Distribution::Gentoo => upgrade_gentoo(ctx),
             Distribution::Suse => upgrade_suse(ctx),
             Distribution::SuseMicro => upgrade_suse_micro(ctx),
@@ -153,23 +242,175 @@ impl Distribution {
             Distribution::Solus => upgrade_solus(ctx),
             Distribution::Exherbo => upgrade_exherbo(ctx),
             Distribution::NixOS => upgrade_nixos(ctx),
+            Distribution::Guix => upgrade_guix_system(ctx),
             Distribution::KDENeon => upgrade_neon(ctx),
             Distribution::Bedrock => update_bedrock(ctx),
+            Distribution::Mageia => upgrade_mageia(ctx),
             Distribution::OpenMandriva => upgrade_openmandriva(ctx),
             Distribution::PCLinuxOS => upgrade_pclinuxos(ctx),
+            Distribution::Photon | Distribution::AzureLinux => upgrade_tdnf(ctx),
+            Distribution::Serpent => upgrade_serpent(ctx),
             Distribution::Nobara => upgrade_nobara(ctx),
         }
     }
 
+    pub fn refresh_mirrors(self, ctx: &ExecutionContext) -> Result<()> {
+        match self {
+            Distribution::Arch => archlinux::refresh_mirrors(ctx),
+            _ => Err(SkipStep(String::from(
+                "Refreshing the mirrorlist is only supported on Arch Linux",
+            ))
+            .into()),
+        }
+    }
+
     pub fn show_summary(self) {
-        if let Distribution::Arch = self {
-            archlinux::show_pacnew();
+        match self {
+            Distribution::Arch => {
+                archlinux::show_pacnew();
+                show_kernel_reboot_recommended(self);
+            }
+            Distribution::Bedrock => show_bedrock_skipped_strata(),
+            Distribution::MX => {
+                show_apt_kept_back();
+                show_kernel_reboot_recommended(self);
+            }
+            Distribution::Debian | Distribution::PopOS | Distribution::KDENeon => show_kernel_reboot_recommended(self),
+            Distribution::Suse | Distribution::OpenSuseTumbleweed => show_zypper_ps(),
+            _ if self.redhat_based() => show_dnf_offline_staged(),
+            _ => (),
         }
     }
 
     pub fn redhat_based(self) -> bool {
-        matches!(self, Distribution::CentOS | Distribution::Fedora)
+        matches!(
+            self,
+            Distribution::CentOS | Distribution::Fedora | Distribution::AmazonLinux
+        )
+    }
+}
+
+/// Runs the system upgrade between the snapshots configured by `pre_upgrade_snapshot`.
+///
+/// A failed snapshot fails the step without upgrading, unless `snapshot_failure_is_warning` is set.
+pub fn with_upgrade_snapshot(ctx: &ExecutionContext, upgrade: impl FnOnce() -> Result<()>) -> Result<()> {
+    let tool = ctx.config().pre_upgrade_snapshot();
+    if tool == PreUpgradeSnapshot::None {
+        return upgrade();
+    }
+
+    let pre_snapshot = match take_snapshot(ctx, tool, None) {
+        Ok(snapshot) => snapshot,
+        Err(e) if ctx.config().snapshot_failure_is_warning() => {
+            print_warning(format!("Failed to take a snapshot before upgrading: {e}"));
+            None
+        }
+        Err(e) => return Err(e.wrap_err("Failed to take a snapshot before upgrading")),
+    };
+
+    let result = upgrade();
+
+    if let Some(pre_snapshot) = pre_snapshot {
+        if matches!(&result, Err(e) if e.downcast_ref::<SkipStep>().is_some()) {
+            // Nothing was upgraded, so the snapshot taken before is of no use
+            if let Err(e) = delete_snapshot(ctx, tool, &pre_snapshot, "pre-upgrade-snapshot") {
+                print_warning(format!("Failed to delete the snapshot taken before upgrading: {e}"));
+            }
+        } else if tool == PreUpgradeSnapshot::Snapper {
+            // Timeshift has no post snapshots, only snapper pairs one with the pre snapshot
+            if let Err(e) = take_snapshot(ctx, tool, Some(&pre_snapshot)) {
+                print_warning(format!("Failed to take a snapshot after upgrading: {e}"));
+            }
+        }
+    }
+
+    result
+}
+
+/// Takes a snapshot and records it for the summary, returning its snapper number or timeshift name.
+///
+/// With `pre_number`, a snapper post snapshot matching that pre snapshot is taken.
+fn take_snapshot(ctx: &ExecutionContext, tool: PreUpgradeSnapshot, pre_number: Option<&str>) -> Result<Option<String>> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    match tool {
+        PreUpgradeSnapshot::None => return Ok(None),
+        PreUpgradeSnapshot::Snapper => {
+            command.arg(require("snapper")?).arg("create");
+            match pre_number {
+                Some(pre_number) => command.args(["--type", "post", "--pre-number", pre_number]),
+                None => command.args(["--type", "pre"]),
+            };
+            command.args(["--print-number", "--description", "topgrade"]);
+        }
+        PreUpgradeSnapshot::Timeshift => {
+            command
+                .arg(require("timeshift")?)
+                .args(["--create", "--comments", "topgrade", "--scripted"]);
+        }
+    }
+
+    if ctx.run_type().dry() {
+        command.status_checked()?;
+        return Ok(None);
     }
+
+    let output = command.output_checked_utf8()?.stdout;
+    let snapshot = match tool {
+        PreUpgradeSnapshot::Timeshift => parse_timeshift_snapshot(&output),
+        _ => Some(output.trim()),
+    };
+
+    let (id, description) = match (tool, pre_number) {
+        (PreUpgradeSnapshot::Timeshift, _) => (
+            "pre-upgrade-snapshot",
+            format!("timeshift {}", snapshot.unwrap_or("unknown")),
+        ),
+        (_, Some(_)) => (
+            "post-upgrade-snapshot",
+            format!("snapper post {}", snapshot.unwrap_or_default()),
+        ),
+        (_, None) => (
+            "pre-upgrade-snapshot",
+            format!("snapper pre {}", snapshot.unwrap_or_default()),
+        ),
+    };
+    ctx.set_summary_note(
+        id,
+        SummaryNote::Info(format!("Snapshot taken around the system upgrade: {description}")),
+    );
+
+    Ok(snapshot.map(String::from))
+}
+
+/// Deletes a snapshot taken by `take_snapshot` and drops its summary note `id`.
+fn delete_snapshot(ctx: &ExecutionContext, tool: PreUpgradeSnapshot, snapshot: &str, id: &str) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    match tool {
+        PreUpgradeSnapshot::None => return Ok(()),
+        PreUpgradeSnapshot::Snapper => command.arg(require("snapper")?).args(["delete", snapshot]),
+        PreUpgradeSnapshot::Timeshift => {
+            command
+                .arg(require("timeshift")?)
+                .args(["--delete", "--snapshot", snapshot, "--scripted"])
+        }
+    };
+    command.status_checked()?;
+
+    ctx.remove_summary_note(id);
+
+    Ok(())
+}
+
+/// Returns the name of the snapshot from the output of `timeshift --create`.
+fn parse_timeshift_snapshot(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Tagged snapshot '"))
+        .and_then(|rest| rest.split('\'').next())
 }
 
 fn update_bedrock(ctx: &ExecutionContext) -> Result<()> {
@@ -177,18 +418,12 @@ fn update_bedrock(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.run_type().execute(sudo).args(["brl", "update"]);
 
-    let output = Command::new("brl").arg("list").output_checked_utf8()?;
-    debug!("brl list: {:?} {:?}", output.stdout, output.stderr);
-
-    for distribution in output.stdout.trim().lines() {
-        debug!("Bedrock distribution {}", distribution);
-        match distribution {
-            "arch" => archlinux::upgrade_arch_linux(ctx)?,
-            "debian" | "ubuntu" | "linuxmint" => upgrade_debian(ctx)?,
-            "centos" | "fedora" => upgrade_redhat(ctx)?,
-            "bedrock" => upgrade_bedrock_strata(ctx)?,
-            _ => {
-                warn!("Unknown distribution {}", distribution);
+    for stratum in bedrock_strata()? {
+        debug!("Bedrock stratum {}", stratum);
+        match bedrock_stratum_upgrader(&stratum) {
+            Some(upgrade) => upgrade(ctx)?,
+            None => {
+                warn!("Unknown distribution {}", stratum);
             }
         }
     }
@@ -196,48 +431,134 @@ fn update_bedrock(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-fn upgrade_alpine_linux(ctx: &ExecutionContext) -> Result<()> {
-    let apk = require("apk")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+/// Lists the enabled Bedrock strata.
+///
+/// Only the first column is used, so that the output of `brl list -v` can be parsed as well.
+fn bedrock_strata() -> Result<Vec<String>> {
+    let output = Command::new("brl").arg("list").output_checked_utf8()?;
+    debug!("brl list: {:?} {:?}", output.stdout, output.stderr);
 
-    ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
+    Ok(parse_bedrock_strata(&output.stdout))
+}
+
+fn parse_bedrock_strata(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+fn bedrock_stratum_upgrader(stratum: &str) -> Option<fn(&ExecutionContext) -> Result<()>> {
+    match stratum {
+        "arch" => Some(archlinux::upgrade_arch_linux),
+        "debian" | "ubuntu" | "linuxmint" => Some(upgrade_debian),
+        "centos" | "fedora" => Some(upgrade_redhat),
+        "void" | "void-musl" => Some(upgrade_void),
+        "alpine" => Some(upgrade_apk_based),
+        "gentoo" => Some(upgrade_gentoo),
+        "opensuse" | "suse" => Some(upgrade_suse),
+        "bedrock" => Some(upgrade_bedrock_strata),
+        _ => None,
+    }
+}
+
+fn show_bedrock_skipped_strata() {
+    let Ok(strata) = bedrock_strata() else {
+        return;
+    };
+
+    let skipped: Vec<String> = strata
+        .into_iter()
+        .filter(|stratum| bedrock_stratum_upgrader(stratum).is_none())
+        .collect();
+
+    if !skipped.is_empty() {
+        println!("\nUnsupported Bedrock strata were skipped: {}", skipped.join(", "));
+    }
 }
 
-fn upgrade_chimera_linux(ctx: &ExecutionContext) -> Result<()> {
+fn upgrade_apk_based(ctx: &ExecutionContext) -> Result<()> {
     let apk = require("apk")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
 
     ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&apk).arg("upgrade");
+    if let Some(args) = ctx.config().apk_arguments() {
+        command.args(args.split_whitespace());
+    }
+    command.status_checked()?;
+
+    // `apk cache clean` fails when no cache directory is configured
+    if ctx.config().cleanup() && Path::new("/etc/apk/cache").exists() {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&apk)
+            .args(["cache", "clean"])
+            .status_checked()?;
+    }
+
+    Ok(())
 }
 
-fn upgrade_wolfi_linux(ctx: &ExecutionContext) -> Result<()> {
-    let apk = require("apk")?;
+fn upgrade_alt_linux(ctx: &ExecutionContext) -> Result<()> {
+    let apt = require("apt-get")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
 
-    ctx.run_type().execute(sudo).arg(&apk).arg("update").status_checked()?;
-    ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").status_checked()
+    ctx.run_type().execute(sudo).arg(&apt).arg("update").status_checked()?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&apt).arg("dist-upgrade");
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+    if let Some(args) = ctx.config().apt_arguments() {
+        command.args(args.split_whitespace());
+    }
+    command.status_checked()?;
+
+    if let Some(update_kernel) = which("update-kernel") {
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(update_kernel);
+        if ctx.config().yes(Step::System) {
+            command.arg("-y");
+        }
+        command.status_checked()?;
+    }
+
+    Ok(())
 }
 
 fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.config().bootc() {
+        if let Some(bootc) = which("bootc") {
+            return upgrade_bootc(ctx, &bootc);
+        }
+    }
+
     if let Some(ostree) = which("rpm-ostree") {
         if ctx.config().rpm_ostree() {
-            let mut command = ctx.run_type().execute(ostree);
-            command.arg("upgrade");
-            return command.status_checked();
+            return upgrade_rpm_ostree(ctx, &ostree);
         }
     };
 
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    if ctx.config().dnf_offline() {
+        if let Some(dnf) = which("dnf") {
+            return upgrade_dnf_offline(ctx, sudo, &dnf);
+        }
+    }
+
+    let pkg_manager = which("dnf").unwrap_or_else(|| Path::new("yum").to_path_buf());
     let mut command = ctx.run_type().execute(sudo);
-    command
-        .arg(which("dnf").unwrap_or_else(|| Path::new("yum").to_path_buf()))
-        .arg(if ctx.config().redhat_distro_sync() {
-            "distro-sync"
-        } else {
-            "upgrade"
-        });
+    command.arg(&pkg_manager).arg(if ctx.config().redhat_distro_sync() {
+        "distro-sync"
+    } else {
+        "upgrade"
+    });
 
     if let Some(args) = ctx.config().dnf_arguments() {
         command.args(args.split_whitespace());
@@ -248,6 +569,135 @@ fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
     }
 
     command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        dnf_cleanup(ctx, sudo, &pkg_manager)?;
+    }
+
+    if ctx.config().dnf_needs_restarting() {
+        if let Some(dnf) = which("dnf") {
+            dnf_needs_restarting(ctx, &dnf);
+        }
+    }
+
+    Ok(())
+}
+
+fn dnf_cleanup(ctx: &ExecutionContext, sudo: &Sudo, pkg_manager: &Path) -> Result<()> {
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(pkg_manager).arg("autoremove");
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+    command.status_checked()?;
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(pkg_manager)
+        .args(["clean", "packages"])
+        .status_checked()
+}
+
+/// Records for the summary whether `dnf needs-restarting` asks for a reboot.
+///
+/// This is only informative, so a failure to check doesn't fail the step.
+fn dnf_needs_restarting(ctx: &ExecutionContext, dnf: &Path) {
+    // The exit code is 1 when a reboot is required, its message is translated
+    match ctx
+        .run_type()
+        .execute(dnf)
+        .args(["needs-restarting", "-r"])
+        .status_code_checked_with_codes(&[1])
+    {
+        Ok(Some(1)) => ctx.set_summary_note(
+            "dnf-reboot-required",
+            SummaryNote::Warning(String::from("A reboot is required to fully apply the updates")),
+        ),
+        Ok(_) => (),
+        Err(e) => debug!("Could not run dnf needs-restarting: {e:?}"),
+    }
+}
+
+/// Downloads the updates so that they are applied on the next reboot.
+fn upgrade_dnf_offline(ctx: &ExecutionContext, sudo: &Sudo, dnf: &Path) -> Result<()> {
+    // The offline upgrade is built into dnf5, but is a plugin with another syntax on dnf4.
+    // Both can be installed, so check which one `dnf` actually points to.
+    let is_dnf5 = dnf.canonicalize().unwrap_or_else(|_| dnf.to_path_buf()).file_name() == Some(OsStr::new("dnf5"));
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(dnf);
+    if is_dnf5 {
+        command.args(["upgrade", "--offline"]);
+    } else {
+        command.args(["offline-upgrade", "download"]);
+    }
+    if let Some(args) = ctx.config().dnf_arguments() {
+        command.args(args.split_whitespace());
+    }
+    command.arg("-y").status_checked()?;
+
+    if !ctx.config().dnf_offline_reboot() {
+        println!("Updates are staged and will be applied on the next reboot");
+        return Ok(());
+    }
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(dnf);
+    if is_dnf5 {
+        command.args(["offline", "reboot"]);
+    } else {
+        command.args(["offline-upgrade", "reboot"]);
+    }
+    command.status_checked()
+}
+
+fn show_dnf_offline_staged() {
+    // systemd's offline update mechanism is triggered by this symlink
+    if Path::new("/system-update").exists() {
+        print_warning("\nOffline updates are staged and will be applied on the next reboot");
+    }
+}
+
+fn upgrade_amazon_linux(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_redhat(ctx)?;
+
+    // Looking for a newer release is best-effort, the system is already upgraded at this point
+    let Some(dnf) = which("dnf") else {
+        return Ok(());
+    };
+    let output = match Command::new(&dnf).arg("check-release-update").output_checked_utf8() {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Could not run dnf check-release-update: {e:?}");
+            return Ok(());
+        }
+    };
+    debug!("dnf check-release-update: {:?} {:?}", output.stdout, output.stderr);
+
+    // The notice about a newer release is printed on stderr
+    let notice = format!("{}{}", output.stdout, output.stderr);
+    if !notice.contains("A newer release") {
+        return Ok(());
+    }
+
+    println!("{}", notice.trim());
+
+    if ctx.config().amazon_linux_release_upgrade() {
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(&dnf).args(["upgrade", "--releasever=latest"]);
+
+        if let Some(args) = ctx.config().dnf_arguments() {
+            command.args(args.split_whitespace());
+        }
+
+        if ctx.config().yes(Step::System) {
+            command.arg("-y");
+        }
+
+        command.status_checked()?;
+    }
+
     Ok(())
 }
 
@@ -282,14 +732,149 @@ fn upgrade_nobara(ctx: &ExecutionContext) -> Result<()> {
     upgrade_command.arg("distro-sync");
 
     upgrade_command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        dnf_cleanup(ctx, sudo, &pkg_manager)?;
+    }
+
     Ok(())
 }
 
 fn upgrade_fedora_immutable(ctx: &ExecutionContext) -> Result<()> {
+    if let Some(ublue_update) = ublue_updater(ctx) {
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        ctx.run_type().execute(sudo).arg(ublue_update).status_checked()?;
+        return Ok(());
+    }
+
+    if ctx.config().bootc() {
+        if let Some(bootc) = which("bootc") {
+            return upgrade_bootc(ctx, &bootc);
+        }
+    }
+
     let ostree = require("rpm-ostree")?;
+    upgrade_rpm_ostree(ctx, &ostree)
+}
+
+fn upgrade_bootc(ctx: &ExecutionContext, bootc: &Path) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    ctx.run_type()
+        .execute(sudo)
+        .arg(bootc)
+        .arg("upgrade")
+        .status_checked()?;
+
+    if ctx.run_type().dry() {
+        return Ok(());
+    }
+
+    let output = Command::new(sudo)
+        .arg(bootc)
+        .args(["status", "--json"])
+        .output_checked_utf8()?;
+
+    match bootc_staged_image(&output.stdout)? {
+        Some(image) => {
+            println!("Staged {image}, reboot to apply");
+            ctx.set_summary_note(
+                "bootc-staged",
+                SummaryNote::Warning(format!("Staged {image}, reboot to apply")),
+            );
+        }
+        None => println!("No new image was staged"),
+    }
+
+    Ok(())
+}
+
+/// The parts of `bootc status --json` needed to tell whether a new image was staged
+#[derive(Deserialize)]
+struct BootcHost {
+    status: BootcHostStatus,
+}
+
+#[derive(Deserialize)]
+struct BootcHostStatus {
+    staged: Option<BootcBootEntry>,
+    booted: Option<BootcBootEntry>,
+}
+
+#[derive(Deserialize)]
+struct BootcBootEntry {
+    image: Option<BootcImageStatus>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BootcImageStatus {
+    image: BootcImageReference,
+    image_digest: String,
+}
+
+#[derive(Deserialize)]
+struct BootcImageReference {
+    image: String,
+}
+
+/// Returns the `image@digest` of the staged deployment, if it differs from the booted one.
+fn bootc_staged_image(status: &str) -> Result<Option<String>> {
+    let host: BootcHost = serde_json::from_str(status)?;
+
+    let booted_digest = host
+        .status
+        .booted
+        .and_then(|entry| entry.image)
+        .map(|image| image.image_digest);
+
+    Ok(host
+        .status
+        .staged
+        .and_then(|entry| entry.image)
+        .filter(|staged| Some(&staged.image_digest) != booted_digest.as_ref())
+        .map(|staged| format!("{}@{}", staged.image.image, staged.image_digest)))
+}
+
+fn upgrade_rpm_ostree(ctx: &ExecutionContext, ostree: &Path) -> Result<()> {
     let mut command = ctx.run_type().execute(ostree);
     command.arg("upgrade");
+    if ctx.config().rpm_ostree_apply_live() {
+        command.arg("--apply-live");
+    }
     command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        // `-p` would discard the deployment we just staged, so only drop the
+        // temporary files and unreferenced objects, keeping the rollback deployment
+        ctx.run_type()
+            .execute(ostree)
+            .args(["cleanup", "-b"])
+            .status_checked()?;
+    }
+
+    Ok(())
+}
+
+/// The update wrapper shipped by Universal Blue images (Bazzite, Bluefin, Aurora), if it should be used.
+///
+/// Besides the system image, it also updates Flatpaks and Distrobox containers.
+fn ublue_updater(ctx: &ExecutionContext) -> Option<PathBuf> {
+    if !ctx.config().ublue_update() {
+        return None;
+    }
+
+    which("uupd").or_else(|| which("ublue-update"))
+}
+
+/// Skip steps that were already handled by the Universal Blue update wrapper during the system step.
+fn skip_if_ublue_updated(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.config().should_run(Step::System)
+        && matches!(ctx.distribution(), Ok(Distribution::FedoraImmutable))
+        && ublue_updater(ctx).is_some()
+    {
+        return Err(SkipStep(String::from("Already updated by the Universal Blue update wrapper")).into());
+    }
+
     Ok(())
 }
 
@@ -301,6 +886,14 @@ fn upgrade_bedrock_strata(ctx: &ExecutionContext) -> Result<()> {
 }
 
 fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_zypper(ctx, ctx.config().suse_mode().unwrap_or(SuseMode::Update))
+}
+
+fn upgrade_opensuse_tumbleweed(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_zypper(ctx, ctx.config().suse_mode().unwrap_or(SuseMode::DistUpgrade))
+}
+
+fn upgrade_zypper(ctx: &ExecutionContext, mode: SuseMode) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     ctx.run_type()
         .execute(sudo)
@@ -309,10 +902,10 @@ fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
 
     let mut cmd = ctx.run_type().execute(sudo);
     cmd.arg("zypper");
-    cmd.arg(if ctx.config().suse_dup() {
-        "dist-upgrade"
-    } else {
-        "update"
+    cmd.arg(match mode {
+        SuseMode::Update => "update",
+        SuseMode::DistUpgrade => "dist-upgrade",
+        SuseMode::Patch => "patch",
     });
     if ctx.config().yes(Step::System) {
         cmd.arg("-y");
@@ -320,25 +913,35 @@ fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
 
     cmd.status_checked()?;
 
-    Ok(())
+    if ctx.config().cleanup() {
+        ctx.run_type()
+            .execute(sudo)
+            .args(["zypper", "clean", "--all"])
+            .status_checked()?;
+
+        // Keeps old kernels from filling up small /boot partitions
+        if let Some(purge_kernels) = which("/usr/sbin/purge-kernels") {
+            ctx.run_type().execute(sudo).arg(purge_kernels).status_checked()?;
+        }
+    }
+
+    Ok(())
 }
 
-fn upgrade_opensuse_tumbleweed(ctx: &ExecutionContext) -> Result<()> {
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    ctx.run_type()
-        .execute(sudo)
-        .args(["zypper", "refresh"])
-        .status_checked()?;
+fn show_zypper_ps() {
+    let Ok(output) = Command::new("zypper").args(["ps", "-s"]).output_checked_utf8() else {
+        return;
+    };
 
-    let mut cmd = ctx.run_type().execute(sudo);
-    cmd.args(["zypper", "dist-upgrade"]);
-    if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+    if zypper_ps_lists_processes(&output.stdout) {
+        println!("\n{}", output.stdout.trim_end());
+        print_warning("Some processes use deleted files, a restart is needed");
     }
+}
 
-    cmd.status_checked()?;
-
-    Ok(())
+/// Whether `zypper ps` found processes using deleted files.
+fn zypper_ps_lists_processes(output: &str) -> bool {
+    output.lines().any(|line| line.trim_start().starts_with("PID"))
 }
 
 fn upgrade_suse_micro(ctx: &ExecutionContext) -> Result<()> {
@@ -351,75 +954,363 @@ fn upgrade_suse_micro(ctx: &ExecutionContext) -> Result<()> {
 
     cmd.arg("dup").status_checked()?;
 
+    if ctx.run_type().dry() {
+        return Ok(());
+    }
+
+    let Ok(output) = Command::new(sudo)
+        .args(["snapper", "--csvout", "list", "--columns", "number,default,active"])
+        .output_checked_utf8()
+    else {
+        warn!("Could not list the snapper snapshots to detect a pending reboot");
+        return Ok(());
+    };
+    if snapper_has_pending_snapshot(&output.stdout) {
+        TRANSACTIONAL_UPDATE_PENDING.store(true, Ordering::Relaxed);
+        ctx.set_summary_note(
+            "transactional-update-pending",
+            SummaryNote::Warning(String::from("A new snapshot was created, reboot to activate it")),
+        );
+    }
+
     Ok(())
 }
 
-fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
+/// Whether the default snapshot, which is booted next, differs from the active one.
+fn snapper_has_pending_snapshot(output: &str) -> bool {
+    output.lines().skip(1).any(|line| {
+        let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+        matches!(columns.as_slice(), [_, "yes", "no"])
+    })
+}
+
+/// Reboots into the snapshot created by `transactional-update`, according to `transactional_update_reboot`.
+///
+/// This is meant to run after all the other steps.
+pub fn transactional_update_reboot(ctx: &ExecutionContext) -> Result<()> {
+    if !TRANSACTIONAL_UPDATE_PENDING.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    match ctx.config().transactional_update_reboot() {
+        TransactionalUpdateReboot::No => return Ok(()),
+        TransactionalUpdateReboot::Ask => {
+            if !prompt_yesno("Reboot into the new snapshot now?")? {
+                return Ok(());
+            }
+        }
+        TransactionalUpdateReboot::Yes => (),
+    }
+
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let mut command = ctx.run_type().execute(sudo);
+    ctx.run_type()
+        .execute(sudo)
+        .args(["transactional-update", "reboot"])
+        .status_checked()
+}
 
-    command.arg(&which("dnf").unwrap()).arg("upgrade");
+/// Warns when the running kernel is older than the newest installed one.
+fn show_kernel_reboot_recommended(distribution: Distribution) {
+    if distribution != Distribution::Arch && Path::new("/var/run/reboot-required").exists() {
+        print_warning("\nA package requested a reboot, reboot recommended");
+        return;
+    }
 
-    if let Some(args) = ctx.config().dnf_arguments() {
-        command.args(args.split_whitespace());
+    let Ok(running) = Command::new("uname").arg("-r").output_checked_utf8() else {
+        return;
+    };
+    let running = running.stdout.trim();
+
+    // Upgrading the package of the running Arch kernel removes its modules
+    if distribution == Distribution::Arch && !Path::new("/usr/lib/modules").join(running).exists() {
+        print_warning(format!(
+            "\nThe running kernel {running} was upgraded, reboot recommended"
+        ));
+        return;
+    }
+
+    let newest = if distribution == Distribution::Arch {
+        newest_arch_kernel(running)
+    } else {
+        newest_boot_kernel(running)
+    };
+    if let Some(newest) = newest {
+        print_warning(format!(
+            "\nThe running kernel {running} is not the newest installed one ({newest}), reboot recommended"
+        ));
     }
+}
+
+/// Returns the version of the kernel package when it differs from the running Arch kernel.
+fn newest_arch_kernel(running: &str) -> Option<String> {
+    let pkgbase = fs::read_to_string(Path::new("/usr/lib/modules").join(running).join("pkgbase")).ok()?;
+    let pkgbase = pkgbase.trim();
+
+    let output = Command::new("pacman")
+        .args(["-Q", pkgbase])
+        .output_checked_utf8()
+        .ok()?;
+    let installed = output.stdout.split_whitespace().nth(1)?;
+    (!arch_kernel_matches(running, pkgbase, installed)).then(|| installed.to_string())
+}
+
+/// Tells whether `uname -r` matches the version of the Arch kernel package `pkgbase`,
+/// e.g. `6.7.4-zen1-1-zen` and `6.7.4.zen1-1` for `linux-zen`.
+fn arch_kernel_matches(running: &str, pkgbase: &str, package_version: &str) -> bool {
+    let running = pkgbase
+        .strip_prefix("linux-")
+        .and_then(|flavor| running.strip_suffix(&format!("-{flavor}")))
+        .unwrap_or(running);
+    running.replace('-', ".") == package_version.replace('-', ".")
+}
+
+/// Returns the newest kernel version in `/boot` when it is newer than the running one.
+///
+/// Only kernels of the same flavor as the running one are considered, e.g. `amd64` in `6.1.0-18-amd64`.
+fn newest_boot_kernel(running: &str) -> Option<String> {
+    let versions: Vec<String> = fs::read_dir("/boot")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("vmlinuz-"))
+                .map(String::from)
+        })
+        .collect();
+
+    newest_kernel_version(running, &versions).map(String::from)
+}
+
+/// Returns the newest of `versions` with the same flavor as `running`, when it is newer than `running`.
+fn newest_kernel_version<'a>(running: &str, versions: &'a [String]) -> Option<&'a str> {
+    let flavor = |version: &str| version.splitn(3, '-').nth(2).unwrap_or_default().to_string();
+    let running_flavor = flavor(running);
+
+    versions
+        .iter()
+        .map(String::as_str)
+        .filter(|version| flavor(version) == running_flavor)
+        .max_by(|a, b| compare_kernel_versions(a, b))
+        .filter(|newest| compare_kernel_versions(newest, running) == CmpOrdering::Greater)
+}
+
+/// Compares kernel versions component by component, numerically where both components are numbers.
+fn compare_kernel_versions(a: &str, b: &str) -> CmpOrdering {
+    let components = |version: &str| {
+        version
+            .split(['.', '-', '+', '~'])
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+
+    for (a, b) in components(a).iter().zip(components(b).iter()) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
+        if ordering != CmpOrdering::Equal {
+            return ordering;
+        }
+    }
+    components(a).len().cmp(&components(b).len())
+}
+
+/// Lists the failed systemd units, user units are suffixed with ` (user)`.
+pub fn failed_systemd_units() -> Vec<String> {
+    let Some(systemctl) = which("systemctl") else {
+        return Vec::new();
+    };
+
+    let mut units = Vec::new();
+    for (args, suffix) in [(&["--failed"][..], ""), (&["--user", "--failed"][..], " (user)")] {
+        match Command::new(&systemctl)
+            .args(args)
+            .args(["--no-legend", "--plain"])
+            .output_checked_utf8()
+        {
+            Ok(output) => units.extend(
+                parse_failed_units(&output.stdout)
+                    .into_iter()
+                    .map(|unit| format!("{unit}{suffix}")),
+            ),
+            Err(e) => debug!("Failed to list the failed systemd units: {e}"),
+        }
+    }
+    units
+}
+
+/// Returns the unit names from the output of `systemctl --failed --no-legend --plain`.
+fn parse_failed_units(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim_start_matches(['●', '*', ' ']).split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+/// Warns about the systemd units that failed since topgrade started.
+pub fn show_new_failed_units(ctx: &ExecutionContext) {
+    let Some(baseline) = ctx.failed_units_baseline() else {
+        return;
+    };
+
+    let failed: Vec<String> = failed_systemd_units()
+        .into_iter()
+        .filter(|unit| !baseline.contains(unit))
+        .collect();
+    if !failed.is_empty() {
+        print_warning(format!(
+            "\nThese systemd units failed during the upgrade: {}",
+            failed.join(", ")
+        ));
+    }
+}
+
+fn upgrade_mageia(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    // Modern Mageia ships dnf alongside urpmi, prefer it when it is installed
+    if let Some(dnf) = which("dnf") {
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(&dnf).arg("distro-sync");
+
+        if let Some(args) = ctx.config().dnf_arguments() {
+            command.args(args.split_whitespace());
+        }
+
+        if ctx.config().yes(Step::System) {
+            command.arg("-y");
+        }
+
+        return command.status_checked();
+    }
+
+    let urpmi = require("urpmi")?;
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&urpmi).arg("--auto-update");
 
     if ctx.config().yes(Step::System) {
-        command.arg("-y");
+        command.arg("--auto");
     }
 
-    command.status_checked()?;
+    command.status_checked()
+}
+
+fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let dnf = require("dnf")?;
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&dnf)
+        .args(openmandriva_args(
+            ctx.config().dnf_arguments(),
+            ctx.config().yes(Step::System),
+        ))
+        .status_checked()?;
+
+    if ctx.config().cleanup() {
+        dnf_cleanup(ctx, sudo, &dnf)?;
+    }
 
     Ok(())
 }
 
+fn openmandriva_args(arguments: Option<&str>, yes: bool) -> Vec<&str> {
+    let mut args = vec!["upgrade"];
+    if let Some(arguments) = arguments {
+        args.extend(arguments.split_whitespace());
+    }
+    if yes {
+        args.push("-y");
+    }
+    args
+}
+
 fn upgrade_pclinuxos(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let mut command_update = ctx.run_type().execute(sudo);
+    let apt_get = require("apt-get")?;
 
-    command_update.arg(&which("apt-get").unwrap()).arg("update");
-
-    if let Some(args) = ctx.config().dnf_arguments() {
-        command_update.args(args.split_whitespace());
+    for args in pclinuxos_args(ctx.config().apt_arguments(), ctx.config().yes(Step::System)) {
+        ctx.run_type().execute(sudo).arg(&apt_get).args(args).status_checked()?;
     }
 
-    if ctx.config().yes(Step::System) {
-        command_update.arg("-y");
+    Ok(())
+}
+
+/// `apt-get update` followed by `apt-get dist-upgrade`, which gets the `apt_arguments` like on Debian.
+fn pclinuxos_args(arguments: Option<&str>, yes: bool) -> [Vec<&str>; 2] {
+    let mut upgrade = vec!["dist-upgrade"];
+    if yes {
+        upgrade.push("-y");
+    }
+    if let Some(arguments) = arguments {
+        upgrade.extend(arguments.split_whitespace());
     }
 
-    command_update.status_checked()?;
+    [vec!["update"], upgrade]
+}
 
-    let mut cmd = ctx.run_type().execute(sudo);
-    cmd.arg(&which("apt-get").unwrap());
-    cmd.arg("dist-upgrade");
+fn upgrade_tdnf(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let tdnf = require("tdnf")?;
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&tdnf)
+        .arg("refresh")
+        .status_checked()?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&tdnf).arg("upgrade");
     if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+        command.arg("-y");
+    }
+    command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&tdnf)
+            .args(["clean", "all"])
+            .status_checked()?;
     }
-    cmd.status_checked()?;
 
     Ok(())
 }
 
 fn upgrade_vanilla(ctx: &ExecutionContext) -> Result<()> {
-    let apx = require("apx")?;
-
-    let mut update = ctx.run_type().execute(&apx);
-    update.args(["update", "--all"]);
-    if ctx.config().yes(Step::System) {
-        update.arg("-y");
+    // Vanilla OS 2 (Orchid) upgrades the system image with ABRoot, apx only manages subsystems
+    if let Some(abroot) = which("abroot") {
+        let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+        ctx.run_type()
+            .execute(sudo)
+            .arg(abroot)
+            .arg("upgrade")
+            .status_checked()?;
     }
-    update.status_checked()?;
 
-    let mut upgrade = ctx.run_type().execute(&apx);
-    update.args(["upgrade", "--all"]);
-    if ctx.config().yes(Step::System) {
-        upgrade.arg("-y");
+    let apx = require("apx")?;
+
+    for args in vanilla_apx_args(ctx.config().yes(Step::System)) {
+        ctx.run_type().execute(&apx).args(args).status_checked()?;
     }
-    upgrade.status_checked()?;
 
     Ok(())
 }
 
+fn vanilla_apx_args(yes: bool) -> [Vec<&'static str>; 2] {
+    [["update", "--all"], ["upgrade", "--all"]].map(|command| {
+        let mut args = command.to_vec();
+        if yes {
+            args.push("-y");
+        }
+        args
+    })
+}
+
 fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let mut command = ctx.run_type().execute(sudo);
@@ -436,6 +1327,34 @@ fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
     }
     command.status_checked()?;
 
+    if ctx.config().cleanup() {
+        let mut command = ctx.run_type().execute(sudo);
+        command.args(["xbps-remove", "-Oo"]);
+        if ctx.config().yes(Step::System) {
+            command.arg("-y");
+        }
+        command.status_checked()?;
+    }
+
+    if let Some(xcheckrestart) = which("xcheckrestart") {
+        if ctx.run_type().dry() {
+            ctx.run_type().execute(sudo).arg(&xcheckrestart).status_checked()?;
+        } else {
+            let output = Command::new(sudo).arg(&xcheckrestart).output_checked_utf8()?;
+            let processes = output.stdout.trim_end();
+            if !processes.is_empty() {
+                println!("{processes}");
+                ctx.set_summary_note("xcheckrestart", SummaryNote::Info(processes.to_string()));
+                ctx.set_summary_note(
+                    "xcheckrestart-warning",
+                    SummaryNote::Warning(String::from(
+                        "Some processes use outdated libraries, a restart is needed",
+                    )),
+                );
+            }
+        }
+    }
+
     Ok(())
 }
 
@@ -478,26 +1397,109 @@ fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
         )
         .status_checked()?;
 
-    Ok(())
-}
+    if ctx.config().gentoo_live_rebuild() && has_live_packages() {
+        if let Some(smart_live_rebuild) = which("smart-live-rebuild") {
+            let mut command = run_type.execute(sudo);
+            command.arg(smart_live_rebuild);
+            if let Some(args) = ctx.config().gentoo_live_rebuild_args() {
+                command.args(args.split_whitespace());
+            }
+            command.status_checked()?;
+        }
+    }
 
-fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
-    let apt = which("apt-fast")
-        .or_else(|| {
-            if which("mist").is_some() {
-                Some(PathBuf::from("mist"))
-            } else {
-                None
+    if ctx.config().cleanup() {
+        if ctx.config().emerge_depclean() {
+            let mut command = run_type.execute(sudo);
+            command.args(["emerge", "--depclean"]);
+            if !ctx.config().yes(Step::System) {
+                command.arg("--ask");
             }
-        })
-        .or_else(|| {
-            if Path::new("/usr/bin/nala").exists() {
-                Some(Path::new("/usr/bin/nala").to_path_buf())
+            command.status_checked()?;
+        }
+
+        if ctx.config().emerge_preserved_rebuild() && has_preserved_libs() {
+            run_type
+                .execute(sudo)
+                .args(["emerge", "@preserved-rebuild"])
+                .status_checked()?;
+        }
+    }
+
+    if ctx.config().glsa_check() {
+        if let Some(glsa_check) = which("glsa-check") {
+            if run_type.dry() {
+                run_type.execute(&glsa_check).args(["-t", "all"]).status_checked()?;
             } else {
-                None
+                // The exit code tells whether the system is affected, which isn't a failure
+                let output = Command::new(&glsa_check)
+                    .args(["-t", "all"])
+                    .output_checked_with_utf8(|_| Ok(()))?;
+                let glsas = parse_glsa_check(&output.stdout);
+                if !glsas.is_empty() {
+                    println!("{}", glsas.join("\n"));
+                    ctx.set_summary_note(
+                        "glsa-check",
+                        SummaryNote::Warning(format!("The system is affected by these GLSAs: {}", glsas.join(", "))),
+                    );
+                }
             }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether any of the installed packages was built from a live ebuild.
+fn has_live_packages() -> bool {
+    glob::glob("/var/db/pkg/*/*/PROPERTIES")
+        .map(|paths| {
+            paths.filter_map(Result::ok).any(|path| {
+                fs::read_to_string(path)
+                    .map(|properties| properties.split_whitespace().any(|property| property == "live"))
+                    .unwrap_or(false)
+            })
         })
-        .unwrap_or_else(|| PathBuf::from("apt-get"));
+        .unwrap_or(false)
+}
+
+/// Whether portage kept libraries around for packages that still need to be rebuilt.
+fn has_preserved_libs() -> bool {
+    fs::read_to_string("/var/lib/portage/preserved_libs_registry")
+        .map(|registry| !matches!(registry.trim(), "" | "{}"))
+        .unwrap_or(false)
+}
+
+/// Returns the IDs of the GLSAs listed by `glsa-check -t`.
+fn parse_glsa_check(output: &str) -> Vec<String> {
+    let glsa_id = Regex::new(r"^\d{6}-\d+$").unwrap();
+
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| glsa_id.is_match(line))
+        .map(String::from)
+        .collect()
+}
+
+fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_apt(ctx, ctx.config().apt_dist_upgrade_mode())
+}
+
+fn upgrade_mx(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_apt(ctx, ctx.config().mx_apt_command())
+}
+
+/// The apt frontend used to upgrade Debian based distributions.
+fn detect_apt_frontend() -> PathBuf {
+    which("apt-fast")
+        .or_else(|| which("mist"))
+        .or_else(|| which("nala"))
+        .unwrap_or_else(|| PathBuf::from("apt-get"))
+}
+
+fn upgrade_apt(ctx: &ExecutionContext, mode: AptUpgradeMode) -> Result<()> {
+    let apt = detect_apt_frontend();
 
     let is_mist = apt.ends_with("mist");
     let is_nala = apt.ends_with("nala");
@@ -521,12 +1523,24 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
             .status_checked_with_codes(&[0, 100])?;
     }
 
+    if let Some(max_removals) = ctx.config().apt_max_removals() {
+        confirm_apt_removals(mode, max_removals)?;
+    }
+
     let mut command = ctx.run_type().execute(sudo);
     command.arg(&apt);
     if is_nala {
+        // nala's `upgrade` performs a full upgrade by default
         command.arg("upgrade");
+        if mode == AptUpgradeMode::Upgrade {
+            command.arg("--no-full");
+        }
     } else {
-        command.arg("dist-upgrade");
+        command.arg(match mode {
+            AptUpgradeMode::FullUpgrade => "full-upgrade",
+            AptUpgradeMode::DistUpgrade => "dist-upgrade",
+            AptUpgradeMode::Upgrade => "upgrade",
+        });
     };
     if ctx.config().yes(Step::System) {
         command.arg("-y");
@@ -550,55 +1564,223 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+fn upgrade_pop_os(ctx: &ExecutionContext) -> Result<()> {
+    upgrade_debian(ctx)?;
+
+    if !ctx.config().pop_upgrade() {
+        return Ok(());
+    }
+
+    // `pop-upgrade` talks to its daemon over D-Bus, so it doesn't need sudo
+    if let Some(pop_upgrade) = which("pop-upgrade") {
+        ctx.run_type()
+            .execute(&pop_upgrade)
+            .args(["release", "update"])
+            .status_checked()?;
+
+        if ctx.config().pop_upgrade_recovery() {
+            ctx.run_type()
+                .execute(&pop_upgrade)
+                .args(["recovery", "upgrade", "from-release"])
+                .status_checked()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Simulates the upgrade and asks for confirmation, even with `--yes`, when it would remove
+/// more than `max_removals` packages.
+fn confirm_apt_removals(mode: AptUpgradeMode, max_removals: usize) -> Result<()> {
+    let output = Command::new("apt-get")
+        .arg("-s")
+        .arg(match mode {
+            AptUpgradeMode::FullUpgrade | AptUpgradeMode::DistUpgrade => "dist-upgrade",
+            AptUpgradeMode::Upgrade => "upgrade",
+        })
+        .output_checked_utf8()?;
+
+    let removals = count_apt_simulated_removals(&output.stdout);
+    debug!("apt would remove {} packages", removals);
+
+    if removals > max_removals {
+        print_warning(format!(
+            "The upgrade would remove {removals} packages, more than the configured maximum of {max_removals}"
+        ));
+        if !prompt_yesno("Proceed with the upgrade?")? {
+            return Err(SkipStep(format!("The upgrade would remove {removals} packages")).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts the packages that an `apt-get -s` run would remove.
+fn count_apt_simulated_removals(output: &str) -> usize {
+    output.lines().filter(|line| line.starts_with("Remv ")).count()
+}
+
+fn show_apt_kept_back() {
+    // Simulating an upgrade doesn't require root
+    let Ok(output) = Command::new("apt-get").args(["-s", "upgrade"]).output_checked_utf8() else {
+        return;
+    };
+
+    let kept_back = parse_apt_kept_back(&output.stdout);
+    if !kept_back.is_empty() {
+        print_warning(format!("\nPackages kept back by apt: {}", kept_back.join(" ")));
+    }
+}
+
+/// Parses the packages listed under "The following packages have been kept back:" in apt output.
+fn parse_apt_kept_back(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("The following packages have been kept back"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .flat_map(|line| line.split_whitespace())
+        .map(String::from)
+        .collect()
+}
+
+/// Tells whether the `clean` command, missing from older versions, is listed by `deb-get help`.
+fn deb_get_supports_clean(deb_get: &Path) -> bool {
+    Command::new(deb_get)
+        .arg("help")
+        .output_checked_with_utf8(|_| Ok(()))
+        .map(|output| {
+            output
+                .stdout
+                .lines()
+                .any(|line| line.split_whitespace().next() == Some("clean"))
+        })
+        .unwrap_or(false)
+}
+
+/// Tells whether the version printed by `deb-get version` accepts the `--yes` flag, added in 0.4.0.
+fn deb_get_supports_yes_flag(version: &str) -> bool {
+    matches!(Version::parse(version.trim()), Ok(version) if version >= Version::new(0, 4, 0))
+}
+
 pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
     let deb_get = require("deb-get")?;
 
     print_separator("deb-get");
 
     ctx.run_type().execute(&deb_get).arg("update").status_checked()?;
-    ctx.run_type().execute(&deb_get).arg("upgrade").status_checked()?;
+
+    let mut command = ctx.run_type().execute(&deb_get);
+    command.arg("upgrade");
+    if ctx.config().yes(Step::DebGet) {
+        let version = Command::new(&deb_get).arg("version").output_checked_utf8();
+        if matches!(version, Ok(output) if deb_get_supports_yes_flag(&output.stdout)) {
+            command.arg("--yes");
+        } else {
+            // Older versions reject the unknown `--yes` flag, the variable is ignored at worst
+            command.env("DEBGET_YES", "1");
+        }
+    }
+    command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        if deb_get_supports_clean(&deb_get) {
+            ctx.run_type().execute(&deb_get).arg("clean").status_checked()?;
+        } else {
+            debug!("This version of deb-get has no clean command");
+        }
+    }
+
+    Ok(())
+}
+
+fn upgrade_solus(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let yes = ctx.config().yes(Step::System);
+
+    // Solus is migrating away from eopkg, prefer its successors when they are installed
+    let package_manager = which("sol")
+        .or_else(|| which("epm"))
+        .unwrap_or_else(|| PathBuf::from("eopkg"));
+
+    let mut cmd = ctx.run_type().execute(sudo);
+    cmd.arg(&package_manager);
+    if yes {
+        cmd.arg("-y");
+    }
+    cmd.arg("update-repo").status_checked()?;
+
+    let mut cmd = ctx.run_type().execute(sudo);
+    cmd.arg(&package_manager);
+    if yes {
+        cmd.arg("-y");
+    }
+    cmd.arg("upgrade").status_checked()?;
 
     if ctx.config().cleanup() {
-        ctx.run_type().execute(&deb_get).arg("clean").status_checked()?;
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&package_manager)
+            .arg("delete-cache")
+            .status_checked()?;
     }
 
     Ok(())
 }
 
-fn upgrade_solus(ctx: &ExecutionContext) -> Result<()> {
+fn upgrade_serpent(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let mut cmd = ctx.run_type().execute(sudo);
-    cmd.arg("eopkg");
+    let moss = require("moss")?;
+
+    // `moss sync -u` refreshes the repository indexes before applying the update
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&moss).args(["sync", "-u"]);
     if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
+        command.arg("-y");
+    }
+    command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&moss)
+            .args(["state", "prune"])
+            .status_checked()?;
     }
-    cmd.arg("upgrade").status_checked()?;
 
     Ok(())
 }
 
 pub fn run_am(ctx: &ExecutionContext) -> Result<()> {
-    let am = require("am")?;
+    run_am_based(ctx, "am", "AM", Step::AM)
+}
+
+pub fn run_appman(ctx: &ExecutionContext) -> Result<()> {
+    run_am_based(ctx, "appman", "appman", Step::AppMan)
+}
 
-    print_separator("AM");
+/// Updates the AppImages managed by AM or appman, which share the same command line.
+fn run_am_based(ctx: &ExecutionContext, binary: &str, name: &str, step: Step) -> Result<()> {
+    let am = require(binary)?;
 
-    let mut am = ctx.run_type().execute(am);
+    print_separator(name);
 
-    if ctx.config().yes(Step::AM) {
-        am.arg("-U");
+    let mut command = ctx.run_type().execute(&am);
+    if ctx.config().yes(step) {
+        command.arg("-U");
     } else {
-        am.arg("-u");
+        command.arg("-u");
     }
+    if let Some(apps) = ctx.config().am_apps() {
+        command.args(apps);
+    }
+    command.status_checked()?;
 
-    am.status_checked()
-}
-
-pub fn run_appman(ctx: &ExecutionContext) -> Result<()> {
-    let appman = require("appman")?;
-
-    print_separator("appman");
+    if ctx.config().cleanup() {
+        ctx.run_type().execute(&am).arg("-c").status_checked()?;
+    }
 
-    ctx.run_type().execute(appman).arg("-u").status_checked()
+    Ok(())
 }
 
 pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
@@ -606,36 +1788,74 @@ pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("pacdef");
 
-    let output = ctx.run_type().execute(&pacdef).arg("version").output_checked()?;
-    let string = String::from_utf8(output.stdout)?;
-    let new_version = string.contains("version: 1");
+    let output = Command::new(&pacdef).arg("version").output_checked_utf8()?;
+    let major = parse_pacdef_major_version(&output.stdout)
+        .ok_or_else(|| eyre::eyre!("Could not parse the pacdef version from {:?}", output.stdout.trim()))?;
+    debug!("pacdef major version {}", major);
 
-    if new_version {
-        let mut cmd = ctx.run_type().execute(&pacdef);
-        cmd.args(["package", "sync"]);
-        if ctx.config().yes(Step::System) {
-            cmd.arg("--noconfirm");
+    // pacdef 1.x moved the package subcommands under `package`, 0.x and 2.x have them at the top level
+    let subcommand = |name: &'static str| -> Vec<&'static str> {
+        if major == 1 {
+            vec!["package", name]
+        } else {
+            vec![name]
         }
-        cmd.status_checked()?;
+    };
 
-        println!();
-        ctx.run_type()
-            .execute(&pacdef)
-            .args(["package", "review"])
-            .status_checked()?;
+    let synced_nothing = if ctx.config().yes(Step::System) && !ctx.run_type().dry() {
+        // Without prompts to answer, the output tells whether sync installed anything
+        let output = Command::new(&pacdef)
+            .args(subcommand("sync"))
+            .arg("--noconfirm")
+            .output_checked_utf8()?;
+        print!("{}", output.stdout);
+        pacdef_synced_nothing(&output.stdout)
     } else {
         let mut cmd = ctx.run_type().execute(&pacdef);
-        cmd.arg("sync");
+        cmd.args(subcommand("sync"));
         if ctx.config().yes(Step::System) {
             cmd.arg("--noconfirm");
         }
-
         cmd.status_checked()?;
+        false
+    };
+    if synced_nothing {
+        debug!("pacdef sync had nothing to do, skipping pacdef review");
+        return Ok(());
+    }
 
-        println!();
-        ctx.run_type().execute(&pacdef).arg("review").status_checked()?;
+    // Reviewing is only needed when some installed packages are not managed by pacdef
+    if !ctx.run_type().dry() {
+        match Command::new(&pacdef)
+            .args(subcommand("unmanaged"))
+            .output_checked_utf8()
+        {
+            Ok(unmanaged) if unmanaged.stdout.trim().is_empty() => {
+                debug!("No unmanaged packages, skipping pacdef review");
+                return Ok(());
+            }
+            Ok(_) => (),
+            Err(e) => debug!("Could not list the packages unmanaged by pacdef: {e:?}"),
+        }
     }
-    Ok(())
+
+    println!();
+    ctx.run_type()
+        .execute(&pacdef)
+        .args(subcommand("review"))
+        .status_checked()
+}
+
+/// Whether the output of `pacdef sync` says that all the managed packages were already installed.
+fn pacdef_synced_nothing(output: &str) -> bool {
+    output.lines().any(|line| line.trim() == "nothing to do")
+}
+
+/// Returns the major version from the output of `pacdef version`.
+fn parse_pacdef_major_version(output: &str) -> Option<u64> {
+    // "pacdef 0.8.2" up to 0.x, "pacdef, version: 1.6.0" since 1.x
+    let version = output.split_whitespace().last()?;
+    version.trim_start_matches('v').split('.').next()?.parse().ok()
 }
 
 pub fn run_pacstall(ctx: &ExecutionContext) -> Result<()> {
@@ -643,16 +1863,40 @@ pub fn run_pacstall(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Pacstall");
 
-    let mut update_cmd = ctx.run_type().execute(&pacstall);
-    let mut upgrade_cmd = ctx.run_type().execute(pacstall);
+    for args in pacstall_args(
+        ctx.config().yes(Step::Pacstall),
+        ctx.config().pacstall_args(),
+        ctx.config().cleanup(),
+    ) {
+        ctx.run_type().execute(&pacstall).args(args).status_checked()?;
+    }
+
+    Ok(())
+}
 
-    if ctx.config().yes(Step::Pacstall) {
-        update_cmd.arg("-P");
-        upgrade_cmd.arg("-P");
+/// The arguments of the Pacstall invocations: the metadata update, the upgrade and the cache cleanup when enabled.
+fn pacstall_args(yes: bool, arguments: Option<&str>, cleanup: bool) -> Vec<Vec<&str>> {
+    let mut flags = vec!["-U", "-Up"];
+    if cleanup {
+        flags.push("-C");
     }
 
-    update_cmd.arg("-U").status_checked()?;
-    upgrade_cmd.arg("-Up").status_checked()
+    flags
+        .into_iter()
+        .map(|flag| {
+            let mut args = Vec::new();
+            if yes {
+                args.push("-P");
+            }
+            args.push(flag);
+            if flag == "-Up" {
+                if let Some(arguments) = arguments {
+                    args.extend(arguments.split_whitespace());
+                }
+            }
+            args
+        })
+        .collect()
 }
 
 pub fn run_packer_nu(ctx: &ExecutionContext) -> Result<()> {
@@ -676,13 +1920,59 @@ pub fn run_packer_nu(ctx: &ExecutionContext) -> Result<()> {
 
 fn upgrade_clearlinux(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let yes = ctx.config().yes(Step::System);
+
     let mut cmd = ctx.run_type().execute(sudo);
     cmd.args(["swupd", "update"]);
-    if ctx.config().yes(Step::System) {
+    if yes {
         cmd.arg("--assume=yes");
     }
     cmd.status_checked()?;
 
+    let third_party_repos = Command::new("swupd")
+        .args(["3rd-party", "list"])
+        .output_checked_utf8()
+        .map(|output| has_swupd_third_party_repos(&output.stdout))
+        .unwrap_or(false);
+    if third_party_repos {
+        let mut cmd = ctx.run_type().execute(sudo);
+        cmd.args(["swupd", "3rd-party", "update"]);
+        if yes {
+            cmd.arg("--assume=yes");
+        }
+        cmd.status_checked()?;
+    }
+
+    if ctx.config().clearlinux_repair() {
+        let mut cmd = ctx.run_type().execute(sudo);
+        cmd.args(["swupd", "repair", "--quick"]);
+        if yes {
+            cmd.arg("--assume=yes");
+        }
+        cmd.status_checked()?;
+    }
+
+    Ok(())
+}
+
+/// Whether the output of `swupd 3rd-party list` contains any repository.
+fn has_swupd_third_party_repos(output: &str) -> bool {
+    output.lines().any(|line| line.trim_start().starts_with("Repository"))
+}
+
+fn upgrade_crux(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let prt_get = require("prt-get")?;
+
+    ctx.run_type().execute(sudo).args(["ports", "-u"]).status_checked()?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&prt_get).arg("sysup");
+    if ctx.config().yes(Step::System) {
+        command.arg("--install-scripts");
+    }
+    command.status_checked()?;
+
     Ok(())
 }
 
@@ -690,35 +1980,63 @@ fn upgrade_exherbo(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     ctx.run_type().execute(sudo).args(["cave", "sync"]).status_checked()?;
 
-    ctx.run_type()
-        .execute(sudo)
-        .args(["cave", "resolve", "world", "-c1", "-Cs", "-km", "-Km", "-x"])
-        .status_checked()?;
+    let commands = exherbo_args(
+        ctx.config().cave_resolve_arguments(),
+        ctx.config().cleanup() && ctx.config().cave_purge(),
+        ctx.config().cave_fix_linkage(),
+        ctx.config().eclectic_config(),
+    );
+    for args in commands {
+        ctx.run_type().execute(sudo).args(args).status_checked()?;
+    }
 
-    if ctx.config().cleanup() {
-        ctx.run_type()
-            .execute(sudo)
-            .args(["cave", "purge", "-x"])
-            .status_checked()?;
+    Ok(())
+}
+
+/// The commands run with sudo after `cave sync`, the update itself followed by the enabled maintenance steps.
+fn exherbo_args(resolve_arguments: &str, purge: bool, fix_linkage: bool, eclectic_config: bool) -> Vec<Vec<&str>> {
+    let mut resolve = vec!["cave", "resolve", "world"];
+    resolve.extend(resolve_arguments.split_whitespace());
+    let mut commands = vec![resolve];
+
+    if purge {
+        commands.push(vec!["cave", "purge", "-x"]);
     }
 
-    ctx.run_type()
-        .execute(sudo)
-        .args(["cave", "fix-linkage", "-x", "--", "-Cs"])
-        .status_checked()?;
+    if fix_linkage {
+        commands.push(vec!["cave", "fix-linkage", "-x", "--", "-Cs"]);
+    }
 
-    ctx.run_type()
-        .execute(sudo)
-        .args(["eclectic", "config", "interactive"])
-        .status_checked()?;
+    if eclectic_config {
+        commands.push(vec!["eclectic", "config", "interactive"]);
+    }
 
-    Ok(())
+    commands
 }
 
 fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let flake_path = ctx.config().nixos_flake_path();
+
+    if let Some(flake_path) = &flake_path {
+        // `--upgrade` is a no-op for flakes, the lock file has to be updated instead
+        let mut command = ctx.run_type().execute("/run/current-system/sw/bin/nix");
+        command
+            .args(["flake", "update"])
+            .current_dir(flake_path)
+            .status_checked()?;
+    }
+
     let mut command = ctx.run_type().execute(sudo);
-    command.args(["/run/current-system/sw/bin/nixos-rebuild", "switch", "--upgrade"]);
+    command
+        .arg("/run/current-system/sw/bin/nixos-rebuild")
+        .arg(ctx.config().nixos_rebuild_action().as_ref());
+
+    if let Some(flake_path) = &flake_path {
+        command.args(["--flake", flake_path]);
+    } else {
+        command.arg("--upgrade");
+    }
 
     if let Some(args) = ctx.config().nix_arguments() {
         command.args(args.split_whitespace());
@@ -735,6 +2053,23 @@ fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+fn upgrade_guix_system(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let guix = require("guix")?;
+
+    // The system is reconfigured with the user's guix, so pull as the user first
+    ctx.run_type().execute(&guix).arg("pull").status_checked()?;
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&guix)
+        .args(["system", "reconfigure"])
+        .arg(ctx.config().guix_system_config())
+        .status_checked()?;
+
+    Ok(())
+}
+
 fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
     // KDE neon is ubuntu based but uses it's own manager, pkcon
     // running apt update with KDE neon is an error
@@ -743,7 +2078,7 @@ fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
     // if that comes up we need to create a Distribution::PackageKit or some such
 
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let pkcon = which("pkcon").unwrap();
+    let pkcon = require("pkcon")?;
     // pkcon ignores update with update and refresh provided together
     ctx.run_type()
         .execute(sudo)
@@ -758,9 +2093,22 @@ fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().cleanup() {
         cmd.arg("--autoremove");
     }
+    let offline = ctx.config().neon_offline_updates();
+    if offline {
+        // The updates are installed on the next boot, as recommended by Plasma
+        cmd.arg("--only-download");
+    }
     // from pkcon man, exit code 5 is 'Nothing useful was done.'
     cmd.status_checked_with_codes(&[5])?;
 
+    if offline {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&pkcon)
+            .arg("offline-trigger")
+            .status_checked_with_codes(&[5])?;
+    }
+
     Ok(())
 }
 
@@ -769,31 +2117,22 @@ fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
 /// 1. This is a redhat-based distribution
 /// 2. This is a debian-based distribution and it is using `nala` as the `apt`
 ///    alternative
-fn should_skip_needrestart() -> Result<()> {
-    let distribution = Distribution::detect()?;
+fn should_skip_needrestart(ctx: &ExecutionContext) -> Result<()> {
+    // When the distribution is unknown, so is whether its package manager runs needrestart
+    let Ok(distribution) = *ctx.distribution() else {
+        return Ok(());
+    };
     let msg = "needrestart will be ran by the package manager";
 
     if distribution.redhat_based() {
         return Err(SkipStep(String::from(msg)).into());
     }
 
-    if matches!(distribution, Distribution::Debian) {
-        let apt = which("apt-fast")
-            .or_else(|| {
-                if which("mist").is_some() {
-                    Some(PathBuf::from("mist"))
-                } else {
-                    None
-                }
-            })
-            .or_else(|| {
-                if Path::new("/usr/bin/nala").exists() {
-                    Some(Path::new("/usr/bin/nala").to_path_buf())
-                } else {
-                    None
-                }
-            })
-            .unwrap_or_else(|| PathBuf::from("apt-get"));
+    if matches!(
+        distribution,
+        Distribution::Debian | Distribution::PopOS | Distribution::MX
+    ) {
+        let apt = detect_apt_frontend();
 
         let is_nala = apt.ends_with("nala");
 
@@ -809,11 +2148,35 @@ pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let needrestart = require("needrestart")?;
 
-    should_skip_needrestart()?;
+    if is_crostini() {
+        return Err(SkipStep(String::from("Should not run in Crostini")).into());
+    }
+
+    should_skip_needrestart(ctx)?;
 
     print_separator("Check for needed restarts");
 
-    ctx.run_type().execute(sudo).arg(needrestart).status_checked()?;
+    let mode = ctx
+        .config()
+        .needrestart_mode()
+        .unwrap_or(if ctx.config().yes(Step::Restarts) {
+            NeedrestartMode::Auto
+        } else {
+            NeedrestartMode::Interactive
+        });
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(needrestart);
+    match mode {
+        NeedrestartMode::Interactive => (),
+        NeedrestartMode::Auto => {
+            command.args(["-r", "a"]);
+        }
+        NeedrestartMode::List => {
+            command.args(["-r", "l"]);
+        }
+    }
+    command.status_checked()?;
 
     Ok(())
 }
@@ -825,6 +2188,10 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(String::from("Should not run in WSL")).into());
     }
 
+    if is_crostini() {
+        return Err(SkipStep(String::from("Should not run in Crostini")).into());
+    }
+
     print_separator("Firmware upgrades");
 
     ctx.run_type()
@@ -832,103 +2199,359 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
         .arg("refresh")
         .status_checked_with_codes(&[2])?;
 
-    let mut updmgr = ctx.run_type().execute(&fwupdmgr);
+    if !ctx.config().firmware_upgrade() {
+        return ctx
+            .run_type()
+            .execute(&fwupdmgr)
+            .arg("get-updates")
+            .status_checked_with_codes(&[2]);
+    }
 
-    if ctx.config().firmware_upgrade() {
+    let exclude = ctx.config().fwupd_exclude_devices();
+    if exclude.is_empty() {
+        let mut updmgr = ctx.run_type().execute(&fwupdmgr);
         updmgr.arg("update");
         if ctx.config().yes(Step::System) {
             updmgr.arg("-y");
         }
+        updmgr.status_checked_with_codes(&[2])?;
+    } else {
+        // Exit code 2 means that there are no updates
+        let updates = Command::new(&fwupdmgr)
+            .args(["get-updates", "--json"])
+            .output_checked_with_utf8(|output| {
+                if output.status.success() || output.status.code() == Some(2) {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            })?;
+        if !updates.status.success() {
+            return Ok(());
+        }
+
+        for device_id in fwupd_device_ids_to_update(&updates.stdout, exclude)? {
+            let mut updmgr = ctx.run_type().execute(&fwupdmgr);
+            updmgr.arg("update").arg(device_id);
+            if ctx.config().yes(Step::System) {
+                updmgr.arg("-y");
+            }
+            updmgr.status_checked_with_codes(&[2])?;
+        }
+    }
+
+    if !ctx.run_type().dry() {
+        let needs_reboot = Command::new(&fwupdmgr)
+            .args(["get-devices", "--json"])
+            .output_checked_utf8()
+            .and_then(|devices| fwupd_needs_reboot(&devices.stdout));
+        if let Ok(true) = needs_reboot {
+            ctx.set_summary_note(
+                "fwupd-reboot-required",
+                SummaryNote::Warning(String::from("A reboot is required to finish the firmware updates")),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The parts of the JSON output of `fwupdmgr get-updates` and `fwupdmgr get-devices` we use.
+///
+/// Every field is optional, as they vary between fwupd versions.
+#[derive(Deserialize)]
+struct FwupdDevices {
+    #[serde(rename = "Devices", default)]
+    devices: Vec<FwupdDevice>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "PascalCase", default)]
+struct FwupdDevice {
+    name: Option<String>,
+    device_id: Option<String>,
+    guid: FwupdGuids,
+    flags: Vec<String>,
+}
+
+/// Older fwupd versions only list a single GUID.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FwupdGuids {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for FwupdGuids {
+    fn default() -> Self {
+        FwupdGuids::Many(Vec::new())
+    }
+}
+
+impl FwupdDevice {
+    fn matches(&self, pattern: &str) -> bool {
+        let guid_matches = match &self.guid {
+            FwupdGuids::One(guid) => guid.eq_ignore_ascii_case(pattern),
+            FwupdGuids::Many(guids) => guids.iter().any(|guid| guid.eq_ignore_ascii_case(pattern)),
+        };
+
+        guid_matches
+            || self.device_id.as_deref() == Some(pattern)
+            || self.name.as_deref().map(|name| name.contains(pattern)).unwrap_or(false)
+    }
+}
+
+/// Returns the IDs of the devices with firmware updates, leaving out the excluded ones.
+fn fwupd_device_ids_to_update(updates: &str, exclude: &[String]) -> Result<Vec<String>> {
+    let updates: FwupdDevices = serde_json::from_str(updates)?;
+
+    Ok(updates
+        .devices
+        .into_iter()
+        .filter(|device| !exclude.iter().any(|pattern| device.matches(pattern)))
+        .filter_map(|device| device.device_id)
+        .collect())
+}
+
+/// Whether a device is waiting for a reboot to finish its firmware update.
+fn fwupd_needs_reboot(devices: &str) -> Result<bool> {
+    let devices: FwupdDevices = serde_json::from_str(devices)?;
+
+    Ok(devices
+        .devices
+        .iter()
+        .any(|device| device.flags.iter().any(|flag| flag == "needs-reboot")))
+}
+
+pub fn run_rpi_eeprom_update(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let rpi_eeprom_update = require("rpi-eeprom-update")?;
+
+    let model = fs::read_to_string("/proc/device-tree/model").unwrap_or_default();
+    if !model.contains("Raspberry Pi") {
+        return Err(SkipStep(String::from("Not running on a Raspberry Pi")).into());
+    }
+
+    print_separator("Raspberry Pi EEPROM");
+
+    if !ctx.config().rpi_eeprom_apply() {
+        // Exit code 1 means that an update is available
+        return ctx
+            .run_type()
+            .execute(sudo)
+            .arg(&rpi_eeprom_update)
+            .status_checked_with_codes(&[1]);
+    }
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&rpi_eeprom_update)
+        .arg("-a")
+        .status_checked()?;
+
+    // Pending updates are staged on the boot partition and flashed on the next boot
+    if ["/boot/firmware/pieeprom.upd", "/boot/pieeprom.upd"]
+        .iter()
+        .any(|path| Path::new(path).exists())
+    {
+        println!("A reboot is required to apply the EEPROM update");
+        ctx.set_summary_note(
+            "rpi-eeprom-reboot-required",
+            SummaryNote::Warning(String::from(
+                "A reboot is required to apply the Raspberry Pi EEPROM update",
+            )),
+        );
     } else {
-        updmgr.arg("get-updates");
+        println!("No reboot required");
     }
-    updmgr.status_checked_with_codes(&[2])
+
+    Ok(())
 }
 
 pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     let flatpak = require("flatpak")?;
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let cleanup = ctx.config().cleanup();
+    skip_if_ublue_updated(ctx)?;
+
+    if !ctx.config().flatpak_system_only() {
+        print_separator("Flatpak User Packages");
+        update_flatpak_installation(ctx, &flatpak, "--user")?;
+    }
+
+    if !ctx.config().flatpak_user_only() {
+        print_separator("Flatpak System Packages");
+        update_flatpak_installation(ctx, &flatpak, "--system")?;
+
+        for installation in ctx.config().flatpak_installations() {
+            print_separator(format!("Flatpak Installation {installation}"));
+            update_flatpak_installation(ctx, &flatpak, &format!("--installation={installation}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates the installation selected by `installation_arg`, and removes its unused refs on cleanup.
+fn update_flatpak_installation(ctx: &ExecutionContext, flatpak: &Path, installation_arg: &str) -> Result<()> {
     let yes = ctx.config().yes(Step::Flatpak);
-    let run_type = ctx.run_type();
-    print_separator("Flatpak User Packages");
 
-    let mut update_args = vec!["update", "--user"];
+    // polkit can't prompt for a password over SSH
+    let use_sudo = installation_arg != "--user"
+        && ctx
+            .config()
+            .flatpak_use_sudo()
+            .unwrap_or_else(|| std::env::var("SSH_CLIENT").is_ok());
+    let sudo = if use_sudo {
+        Some(require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?)
+    } else {
+        None
+    };
+    let command = || match sudo {
+        Some(sudo) => {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(flatpak);
+            command
+        }
+        None => ctx.run_type().execute(flatpak),
+    };
+
+    if ctx.config().flatpak_repair() {
+        command().args(["repair", installation_arg]).status_checked()?;
+    }
+
+    let mut update = command();
+    update.args(["update", installation_arg]);
     if yes {
-        update_args.push("-y");
+        update.arg("-y");
     }
-    run_type.execute(&flatpak).args(&update_args).status_checked()?;
+    update.status_checked()?;
 
-    if cleanup {
-        let mut cleanup_args = vec!["uninstall", "--user", "--unused"];
+    if ctx.config().cleanup() {
+        let mut cleanup = command();
+        cleanup.args(["uninstall", installation_arg, "--unused"]);
         if yes {
-            cleanup_args.push("-y");
+            cleanup.arg("-y");
         }
-        run_type.execute(&flatpak).args(&cleanup_args).status_checked()?;
+        cleanup.status_checked()?;
     }
 
-    print_separator("Flatpak System Packages");
-    if ctx.config().flatpak_use_sudo() || std::env::var("SSH_CLIENT").is_ok() {
-        let mut update_args = vec!["update", "--system"];
-        if yes {
-            update_args.push("-y");
-        }
-        run_type
-            .execute(sudo)
-            .arg(&flatpak)
-            .args(&update_args)
-            .status_checked()?;
-        if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
-            }
-            run_type
+    Ok(())
+}
+
+pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let snap = require("snap")?;
+
+    if is_crostini() {
+        return Err(SkipStep(String::from("Should not run in Crostini")).into());
+    }
+
+    if !PathBuf::from("/var/snapd.socket").exists() && !PathBuf::from("/run/snapd.socket").exists() {
+        return Err(SkipStep(String::from("Snapd socket does not exist")).into());
+    }
+    print_separator("snap");
+
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&snap)
+        .arg("refresh")
+        .status_checked()?;
+
+    let list = Command::new(&snap).args(["list", "--all"]).output_checked_utf8()?;
+
+    let held = held_snaps(&list.stdout);
+    if !held.is_empty() {
+        print_warning(format!("Held snaps, not refreshed: {}", held.join(", ")));
+    }
+
+    if ctx.config().cleanup() {
+        for (name, revision) in snap_revisions_to_remove(&list.stdout, ctx.config().snap_retain()) {
+            ctx.run_type()
                 .execute(sudo)
-                .arg(flatpak)
-                .args(&cleanup_args)
+                .arg(&snap)
+                .arg("remove")
+                .arg(name)
+                .arg(format!("--revision={revision}"))
                 .status_checked()?;
         }
-    } else {
-        let mut update_args = vec!["update", "--system"];
-        if yes {
-            update_args.push("-y");
-        }
-        run_type.execute(&flatpak).args(&update_args).status_checked()?;
-        if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
-            }
-            run_type.execute(flatpak).args(&cleanup_args).status_checked()?;
-        }
     }
 
     Ok(())
 }
 
-pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
-    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    let snap = require("snap")?;
+/// The name, revision and notes of each revision listed by `snap list --all`.
+fn snap_list_entries(list: &str) -> impl Iterator<Item = (&str, &str, Vec<&str>)> {
+    list.lines().skip(1).filter_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        let notes = columns.last()?.split(',').collect();
+        Some((*columns.first()?, *columns.get(2)?, notes))
+    })
+}
 
-    if !PathBuf::from("/var/snapd.socket").exists() && !PathBuf::from("/run/snapd.socket").exists() {
-        return Err(SkipStep(String::from("Snapd socket does not exist")).into());
+/// Returns the names of the snaps held back from refreshing, from the output of `snap list --all`.
+fn held_snaps(list: &str) -> Vec<String> {
+    snap_list_entries(list)
+        .filter(|(_, _, notes)| notes.contains(&"held"))
+        .map(|(name, _, _)| name.to_string())
+        .collect()
+}
+
+/// Returns the name and revision of the disabled revisions to remove, from the output of `snap list --all`.
+///
+/// `retain` is the number of revisions kept for each snap, including the active one.
+fn snap_revisions_to_remove(list: &str, retain: usize) -> Vec<(String, String)> {
+    let mut disabled: Vec<(&str, &str)> = snap_list_entries(list)
+        .filter(|(_, _, notes)| notes.contains(&"disabled"))
+        .map(|(name, revision, _)| (name, revision))
+        .collect();
+    // Newest revisions first, sideloaded revisions like `x1` are the oldest
+    disabled.sort_by_key(|(name, revision)| (*name, Reverse(revision.parse::<u64>().unwrap_or(0))));
+
+    let mut to_remove = Vec::new();
+    let mut kept = 0;
+    for (i, (name, revision)) in disabled.iter().enumerate() {
+        if i == 0 || disabled[i - 1].0 != *name {
+            // The active revision
+            kept = 1;
+        }
+
+        if kept < retain {
+            kept += 1;
+        } else {
+            to_remove.push((name.to_string(), revision.to_string()));
+        }
     }
-    print_separator("snap");
 
-    ctx.run_type().execute(sudo).arg(snap).arg("refresh").status_checked()
+    to_remove
 }
 
 pub fn run_pihole_update(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let pihole = require("pihole")?;
-    Path::new("/opt/pihole/update.sh").require()?;
+
+    let version = Command::new(&pihole).arg("version").output_checked_utf8()?;
+    let major_version = parse_pihole_major_version(&version.stdout);
+    // Pi-hole v6 doesn't ship the update script anymore
+    if major_version.map(|major| major < 6).unwrap_or(true) {
+        Path::new("/opt/pihole/update.sh").require()?;
+    }
 
     print_separator("pihole");
 
     ctx.run_type().execute(sudo).arg(pihole).arg("-up").status_checked()
 }
 
+/// Returns the major version of Pi-hole from the output of `pihole version`.
+fn parse_pihole_major_version(output: &str) -> Option<u32> {
+    // "Pi-hole version is v5.18.2" up to v5, "Core version is v6.0.4" since v6
+    output.lines().find_map(|line| {
+        let version = line
+            .trim()
+            .strip_prefix("Pi-hole version is v")
+            .or_else(|| line.trim().strip_prefix("Core version is v"))?;
+        version.split('.').next()?.parse().ok()
+    })
+}
+
 pub fn run_protonup_update(ctx: &ExecutionContext) -> Result<()> {
     let protonup = require("protonup")?;
 
@@ -945,6 +2568,7 @@ pub fn run_protonup_update(ctx: &ExecutionContext) -> Result<()> {
 
 pub fn run_distrobox_update(ctx: &ExecutionContext) -> Result<()> {
     let distrobox = require("distrobox")?;
+    skip_if_ublue_updated(ctx)?;
 
     print_separator("Distrobox");
     match (
@@ -968,6 +2592,118 @@ pub fn run_distrobox_update(ctx: &ExecutionContext) -> Result<()> {
     .status_checked()
 }
 
+/// Returns the containers to upgrade concurrently, or `None` when a single `distrobox upgrade` is enough.
+pub fn distrobox_parallel_containers(ctx: &ExecutionContext) -> Option<Vec<String>> {
+    let distrobox = which("distrobox")?;
+    skip_if_ublue_updated(ctx).ok()?;
+
+    // The concurrent upgrades have no stdin, so sudo could not ask for the password of `--root`
+    if ctx.config().distrobox_root() {
+        return None;
+    }
+
+    match ctx.config().distrobox_containers() {
+        Some(containers) if containers.len() > 1 => Some(containers.clone()),
+        Some(_) => None,
+        None => {
+            ctx.config().distrobox_parallel()?;
+
+            let output = Command::new(distrobox)
+                .args(["list", "--no-color"])
+                .output_checked_utf8()
+                .ok()?;
+            Some(parse_distrobox_list(&output.stdout))
+        }
+    }
+}
+
+/// Returns the container names from the output of `distrobox list --no-color`.
+fn parse_distrobox_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split('|').nth(1))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Upgrades the containers concurrently, at most `distrobox_parallel` at a time.
+///
+/// The output of each container is printed once its upgrade is done, prefixed with its name.
+pub fn upgrade_distrobox_containers(ctx: &ExecutionContext, containers: &[String]) -> HashMap<String, Result<()>> {
+    print_separator("Distrobox");
+
+    let Some(distrobox) = which("distrobox") else {
+        return HashMap::new();
+    };
+
+    if ctx.run_type().dry() {
+        return containers
+            .iter()
+            .map(|container| (container.clone(), upgrade_distrobox_container(ctx, container)))
+            .collect();
+    }
+
+    let futures_iterator = containers.iter().map(|container| {
+        let distrobox = &distrobox;
+        async move {
+            let mut command = AsyncCommand::new(distrobox);
+            command.stdin(Stdio::null()).arg("upgrade").arg(container);
+
+            let result = match command.output().await {
+                Ok(output) => {
+                    let prefix = style(format!("[{container}]")).bold();
+                    for line in String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .chain(String::from_utf8_lossy(&output.stderr).lines())
+                    {
+                        println!("{prefix} {line}");
+                    }
+
+                    if output.status.success() {
+                        Ok(())
+                    } else {
+                        Err(
+                            TopgradeError::ProcessFailed(format!("distrobox upgrade {container}"), output.status)
+                                .into(),
+                        )
+                    }
+                }
+                Err(e) => Err(e.into()),
+            };
+
+            (container.clone(), result)
+        }
+    });
+
+    let limit = ctx.config().distrobox_parallel().unwrap_or(containers.len()).max(1);
+    let runtime = match runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let error = e.to_string();
+            return containers
+                .iter()
+                .map(|container| (container.clone(), Err(eyre::eyre!("{error}"))))
+                .collect();
+        }
+    };
+    runtime.block_on(async { iter(futures_iterator).buffer_unordered(limit).collect().await })
+}
+
+/// Upgrades a single container, used for retries after `upgrade_distrobox_containers`.
+pub fn upgrade_distrobox_container(ctx: &ExecutionContext, container: &str) -> Result<()> {
+    let distrobox = require("distrobox")?;
+
+    let mut command = ctx.run_type().execute(distrobox);
+    command.arg("upgrade").arg(container);
+    if ctx.config().distrobox_root() {
+        command.arg("--root");
+    }
+    command.status_checked()
+}
+
 pub fn run_dkp_pacman_update(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
     let dkp_pacman = require("dkp-pacman")?;
@@ -997,6 +2733,7 @@ pub fn run_config_update(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep("Skipped in --yes".to_string()).into());
     }
 
+    // Only the first tool found is run, in this order: etc-update (Gentoo), pacdiff (Arch), rpmconf (Fedora)
     if let Ok(etc_update) = require("etc-update") {
         print_separator("Configuration update");
         ctx.run_type().execute(sudo).arg(etc_update).status_checked()?;
@@ -1007,6 +2744,14 @@ pub fn run_config_update(ctx: &ExecutionContext) -> Result<()> {
 
         print_separator("Configuration update");
         ctx.execute_elevated(&pacdiff, false)?.status_checked()?;
+    } else if let Ok(rpmconf) = require("rpmconf") {
+        print_separator("Configuration update");
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(rpmconf).arg("-a");
+        if let Ok(diffprog) = std::env::var("DIFFPROG") {
+            command.arg(format!("--frontend={diffprog}"));
+        }
+        command.status_checked()?;
     }
 
     Ok(())
@@ -1049,11 +2794,11 @@ pub fn run_waydroid(ctx: &ExecutionContext) -> Result<()> {
     // Session:        STOPPED
     // Vendor type:    MAINLINE
     // ```
-    let session = status
-        .stdout
-        .lines()
-        .find(|line| line.contains("Session:"))
-        .expect("the output of `waydroid status` should contain `Session:`");
+    let Some(session) = status.stdout.lines().find(|line| line.contains("Session:")) else {
+        // `waydroid status` prints an error instead, e.g. when the container service isn't installed
+        debug!("Unexpected output of `waydroid status`: {}", status.stdout);
+        return Err(SkipStep(String::from("Could not determine the Waydroid session status")).into());
+    };
     let is_container_running = session.contains("RUNNING");
     let assume_yes = ctx.config().yes(Step::Waydroid);
 
@@ -1070,7 +2815,14 @@ pub fn run_waydroid(ctx: &ExecutionContext) -> Result<()> {
         .execute(sudo)
         .arg(&waydroid)
         .arg("upgrade")
-        .status_checked()
+        .status_checked()?;
+
+    if is_container_running && ctx.config().waydroid_restart_session() {
+        // `waydroid session start` only returns once the session stops, so leave it running in the background
+        ctx.run_type().execute(&waydroid).args(["session", "start"]).spawn()?;
+    }
+
+    Ok(())
 }
 
 pub fn run_auto_cpufreq(ctx: &ExecutionContext) -> Result<()> {
@@ -1098,6 +2850,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_bedrock_strata() {
+        assert_eq!(
+            parse_bedrock_strata("bedrock\narch\nvoid\n"),
+            ["bedrock", "arch", "void"]
+        );
+        assert_eq!(
+            parse_bedrock_strata("bedrock  (global)\narch     (enabled)\n\nalpine   (enabled)\n"),
+            ["bedrock", "arch", "alpine"]
+        );
+    }
+
+    #[test]
+    fn test_adelie() {
+        test_template(include_str!("os_release/adelie"), Distribution::Alpine);
+    }
+
+    #[test]
+    fn test_postmarketos() {
+        test_template(include_str!("os_release/postmarketos"), Distribution::Alpine);
+    }
+
     #[test]
     fn test_wolfi() {
         test_template(include_str!("os_release/wolfi"), Distribution::Wolfi);
@@ -1124,6 +2898,77 @@ mod tests {
         test_template(include_str!("os_release/clearlinux"), Distribution::ClearLinux);
     }
 
+    #[test]
+    fn test_has_swupd_third_party_repos() {
+        assert!(!has_swupd_third_party_repos("No 3rd-party repositories found\n"));
+        assert!(has_swupd_third_party_repos(
+            "Repository: my-repo\nURL: https://example.com/my-repo\n"
+        ));
+    }
+
+    #[test]
+    fn test_mx() {
+        test_template(include_str!("os_release/mx"), Distribution::MX);
+    }
+
+    #[test]
+    fn test_parse_apt_kept_back() {
+        let output = "Reading package lists... Done
+Building dependency tree... Done
+Reading state information... Done
+Calculating upgrade... Done
+The following packages have been kept back:
+  libsystemd0 systemd
+  udev
+The following packages will be upgraded:
+  curl libcurl4
+2 upgraded, 0 newly installed, 0 to remove and 3 not upgraded.
+";
+        assert_eq!(parse_apt_kept_back(output), ["libsystemd0", "systemd", "udev"]);
+    }
+
+    #[test]
+    fn test_parse_apt_nothing_kept_back() {
+        let output = "Reading package lists... Done
+Building dependency tree... Done
+Reading state information... Done
+Calculating upgrade... Done
+0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.
+";
+        assert!(parse_apt_kept_back(output).is_empty());
+    }
+
+    #[test]
+    fn test_count_apt_simulated_removals() {
+        let output = "NOTE: This is only a simulation!
+      apt-get needs root privileges for real execution.
+      Keep also in mind that locking is deactivated,
+      so don't depend on the relevance to the real current situation!
+Reading package lists... Done
+Building dependency tree... Done
+Reading state information... Done
+Calculating upgrade... Done
+The following packages will be REMOVED:
+  libfoo1 libbar2
+The following NEW packages will be installed:
+  libfoo2
+The following packages will be upgraded:
+  foo
+1 upgraded, 1 newly installed, 2 to remove and 0 not upgraded.
+Remv libfoo1 [1.0-1]
+Remv libbar2 [2.0-3]
+Inst libfoo2 (2.0-1 Debian:unstable [amd64])
+Inst foo [1.0-1] (2.0-1 Debian:unstable [amd64])
+Conf libfoo2 (2.0-1 Debian:unstable [amd64])
+Conf foo (2.0-1 Debian:unstable [amd64])
+";
+        assert_eq!(count_apt_simulated_removals(output), 2);
+        assert_eq!(
+            count_apt_simulated_removals("0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n"),
+            0
+        );
+    }
+
     #[test]
     fn test_debian() {
         test_template(include_str!("os_release/debian"), Distribution::Debian);
@@ -1144,6 +2989,22 @@ mod tests {
         test_template(include_str!("os_release/opensuse"), Distribution::Suse);
     }
 
+    #[test]
+    fn test_zypper_ps_lists_processes() {
+        assert!(!zypper_ps_lists_processes("No processes using deleted files found.\n"));
+
+        let output = "The following running processes use deleted files:
+
+PID  | PPID | UID | User | Command | Service
+-----+------+-----+------+---------+--------
+1234 | 1    | 0   | root | sshd    | sshd
+
+You may wish to restart these processes.
+See 'man zypper' for information about the meaning of values in the above table.
+";
+        assert!(zypper_ps_lists_processes(output));
+    }
+
     #[test]
     fn test_oraclelinux() {
         test_template(include_str!("os_release/oracle"), Distribution::CentOS);
@@ -1195,6 +3056,11 @@ mod tests {
         test_template(include_str!("os_release/amazon_linux"), Distribution::CentOS);
     }
 
+    #[test]
+    fn test_amazon_linux_2023() {
+        test_template(include_str!("os_release/amazon_linux_2023"), Distribution::AmazonLinux);
+    }
+
     #[test]
     fn test_nixos() {
         test_template(include_str!("os_release/nixos"), Distribution::NixOS);
@@ -1220,6 +3086,11 @@ mod tests {
         test_template(include_str!("os_release/garuda"), Distribution::Arch);
     }
 
+    #[test]
+    fn test_pop_os() {
+        test_template(include_str!("os_release/pop"), Distribution::PopOS);
+    }
+
     #[test]
     fn test_pureos() {
         test_template(include_str!("os_release/pureos"), Distribution::Debian);
@@ -1235,13 +3106,316 @@ mod tests {
         test_template(include_str!("os_release/vanilla"), Distribution::Vanilla);
     }
 
+    #[test]
+    fn test_vanilla_2() {
+        test_template(include_str!("os_release/vanilla2"), Distribution::Vanilla);
+    }
+
+    #[test]
+    fn test_vanilla_apx_args() {
+        assert_eq!(
+            vanilla_apx_args(true),
+            [vec!["update", "--all", "-y"], vec!["upgrade", "--all", "-y"]]
+        );
+        assert_eq!(
+            vanilla_apx_args(false),
+            [vec!["update", "--all"], vec!["upgrade", "--all"]]
+        );
+    }
+
     #[test]
     fn test_solus() {
         test_template(include_str!("os_release/solus"), Distribution::Solus);
     }
 
+    #[test]
+    fn test_mageia() {
+        test_template(include_str!("os_release/mageia"), Distribution::Mageia);
+    }
+
+    #[test]
+    fn test_photon() {
+        test_template(include_str!("os_release/photon"), Distribution::Photon);
+    }
+
+    #[test]
+    fn test_azurelinux() {
+        test_template(include_str!("os_release/azurelinux"), Distribution::AzureLinux);
+    }
+
+    #[test]
+    fn test_guix() {
+        test_template(include_str!("os_release/guix"), Distribution::Guix);
+    }
+
+    #[test]
+    fn test_altlinux() {
+        test_template(include_str!("os_release/altlinux"), Distribution::AltLinux);
+    }
+
+    #[test]
+    fn test_crux() {
+        test_template(include_str!("os_release/crux"), Distribution::Crux);
+    }
+
+    #[test]
+    fn test_serpentos() {
+        test_template(include_str!("os_release/serpentos"), Distribution::Serpent);
+    }
+
+    #[test]
+    fn test_openeuler() {
+        test_template(include_str!("os_release/openeuler"), Distribution::CentOS);
+    }
+
     #[test]
     fn test_nobara() {
         test_template(include_str!("os_release/nobara"), Distribution::Nobara);
     }
+
+    #[test]
+    fn test_bootc_staged_image() {
+        assert_eq!(
+            bootc_staged_image(include_str!("bootc_status/staged.json")).unwrap(),
+            Some(String::from(
+                "quay.io/fedora/fedora-bootc:41@sha256:6b1a2d8e58cbf2c4b5b9b4e1c1f3c8c1d6f0e9a7a0b2a8d7e6c5b4a3f2e1d0c9"
+            ))
+        );
+        assert_eq!(
+            bootc_staged_image(include_str!("bootc_status/up_to_date.json")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_snapper_has_pending_snapshot() {
+        let pending = "number,default,active\n0,no,no\n41,no,no\n42,no,yes\n43,yes,no\n";
+        assert!(snapper_has_pending_snapshot(pending));
+
+        let booted = "number,default,active\n0,no,no\n41,no,no\n42,yes,yes\n";
+        assert!(!snapper_has_pending_snapshot(booted));
+    }
+
+    #[test]
+    fn test_parse_glsa_check() {
+        let output = "This system is affected by the following GLSAs:\n202401-01\n202405-29\n";
+        assert_eq!(parse_glsa_check(output), vec!["202401-01", "202405-29"]);
+
+        let output = "This system is not affected by any of the listed GLSAs\n";
+        assert!(parse_glsa_check(output).is_empty());
+    }
+
+    #[test]
+    fn test_exherbo_args() {
+        assert_eq!(
+            exherbo_args("-c1 -Cs -km -Km -x", true, true, true),
+            [
+                vec!["cave", "resolve", "world", "-c1", "-Cs", "-km", "-Km", "-x"],
+                vec!["cave", "purge", "-x"],
+                vec!["cave", "fix-linkage", "-x", "--", "-Cs"],
+                vec!["eclectic", "config", "interactive"],
+            ]
+        );
+
+        assert_eq!(
+            exherbo_args("-c1 -Cs -x", false, false, false),
+            [vec!["cave", "resolve", "world", "-c1", "-Cs", "-x"]]
+        );
+    }
+
+    #[test]
+    fn test_openmandriva_args() {
+        assert_eq!(
+            openmandriva_args(Some("--refresh --best"), true),
+            ["upgrade", "--refresh", "--best", "-y"]
+        );
+        assert_eq!(openmandriva_args(None, false), ["upgrade"]);
+    }
+
+    #[test]
+    fn test_pclinuxos_args() {
+        assert_eq!(
+            pclinuxos_args(Some("--fix-broken"), true),
+            [vec!["update"], vec!["dist-upgrade", "-y", "--fix-broken"]]
+        );
+    }
+
+    #[test]
+    fn test_pacstall_args() {
+        assert_eq!(
+            pacstall_args(true, Some("--keep"), true),
+            [vec!["-P", "-U"], vec!["-P", "-Up", "--keep"], vec!["-P", "-C"]]
+        );
+        assert_eq!(pacstall_args(false, None, false), [vec!["-U"], vec!["-Up"]]);
+    }
+
+    #[test]
+    fn test_deb_get_supports_yes_flag() {
+        assert!(deb_get_supports_yes_flag("0.4.5\n"));
+        assert!(!deb_get_supports_yes_flag("0.3.9\n"));
+        assert!(!deb_get_supports_yes_flag("unknown\n"));
+    }
+
+    #[test]
+    fn test_held_snaps() {
+        assert_eq!(held_snaps(include_str!("snap/list_all")), ["firefox", "snapd"]);
+    }
+
+    #[test]
+    fn test_snap_revisions_to_remove() {
+        let list = include_str!("snap/list_all");
+
+        assert_eq!(
+            snap_revisions_to_remove(list, 1),
+            [
+                (String::from("core"), String::from("17200")),
+                (String::from("core22"), String::from("1564")),
+                (String::from("core22"), String::from("1380")),
+                (String::from("gnome-42-2204"), String::from("176")),
+            ]
+        );
+        assert_eq!(
+            snap_revisions_to_remove(list, 2),
+            [(String::from("core22"), String::from("1380"))]
+        );
+        assert!(snap_revisions_to_remove(list, 3).is_empty());
+    }
+
+    #[test]
+    fn test_fwupd_device_ids_to_update() {
+        let updates = include_str!("fwupd/get_updates.json");
+
+        assert_eq!(
+            fwupd_device_ids_to_update(updates, &[]).unwrap(),
+            [
+                "362301da643102b9f38477387e2193e57abaa590",
+                "2c47a4d8dd5c1b1f4c4e8f7b0a9d3e6c5b2a1f08"
+            ]
+        );
+        assert_eq!(
+            fwupd_device_ids_to_update(updates, &[String::from("WD19")]).unwrap(),
+            ["362301da643102b9f38477387e2193e57abaa590"]
+        );
+        assert_eq!(
+            fwupd_device_ids_to_update(updates, &[String::from("F8BA2887-9411-5C36-9CEE-88995BB39731")]).unwrap(),
+            ["2c47a4d8dd5c1b1f4c4e8f7b0a9d3e6c5b2a1f08"]
+        );
+        assert!(fwupd_device_ids_to_update("{}", &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fwupd_needs_reboot() {
+        assert!(fwupd_needs_reboot(include_str!("fwupd/get_devices.json")).unwrap());
+        assert!(!fwupd_needs_reboot(r#"{"Devices": [{"Name": "Samsung SSD 980 PRO 1TB"}]}"#).unwrap());
+    }
+
+    #[test]
+    fn test_parse_pihole_major_version() {
+        let v5 = "  Pi-hole version is v5.18.2 (Latest: v5.18.3)\n  AdminLTE version is v5.21 (Latest: v5.21)\n  FTL version is v5.25.1 (Latest: v5.25.2)\n";
+        assert_eq!(parse_pihole_major_version(v5), Some(5));
+
+        let v6 = "Core version is v6.0.4 (Latest: v6.0.5)\nWeb version is v6.0.1 (Latest: v6.0.2)\nFTL version is v6.0.2 (Latest: v6.0.3)\n";
+        assert_eq!(parse_pihole_major_version(v6), Some(6));
+
+        assert_eq!(parse_pihole_major_version(""), None);
+    }
+
+    #[test]
+    fn test_parse_distrobox_list() {
+        let output = "ID           | NAME                 | STATUS             | IMAGE                         \n\
+                      d1b4ef8b44a3 | arch                 | Up 2 hours         | quay.io/toolbx/arch-toolbox:latest\n\
+                      5e3f0c2a9b17 | ubuntu-22.04         | Exited (0) 3 days ago | quay.io/toolbx/ubuntu-toolbox:22.04\n";
+        assert_eq!(parse_distrobox_list(output), ["arch", "ubuntu-22.04"]);
+        assert!(parse_distrobox_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_timeshift_snapshot() {
+        let output = "Estimating system size...\n\
+                      Creating new snapshot...(RSYNC)\n\
+                      Saving to device: /dev/sda2, mounted at path: /run/timeshift/backup\n\
+                      Synching files with rsync...\n\
+                      Created control file: /run/timeshift/backup/timeshift/snapshots/2024-03-01_10-15-42/info.json\n\
+                      RSYNC Snapshot saved successfully (41s)\n\
+                      Tagged snapshot '2024-03-01_10-15-42': ondemand\n";
+        assert_eq!(parse_timeshift_snapshot(output), Some("2024-03-01_10-15-42"));
+        assert_eq!(parse_timeshift_snapshot("E: Failed to create snapshot\n"), None);
+    }
+
+    #[test]
+    fn test_parse_failed_units() {
+        let output = "bluetooth.service loaded failed failed Bluetooth service\n\
+                      ● nfs-server.service loaded failed failed NFS server and services\n";
+        assert_eq!(parse_failed_units(output), ["bluetooth.service", "nfs-server.service"]);
+        assert!(parse_failed_units("").is_empty());
+    }
+
+    #[test]
+    fn test_compare_kernel_versions() {
+        assert_eq!(
+            compare_kernel_versions("5.10.0-9-amd64", "5.10.0-10-amd64"),
+            CmpOrdering::Less
+        );
+        assert_eq!(
+            compare_kernel_versions("6.1.0-21-amd64", "6.1.0-18-amd64"),
+            CmpOrdering::Greater
+        );
+        assert_eq!(
+            compare_kernel_versions("6.5.0-15-generic", "6.5.0-15-generic"),
+            CmpOrdering::Equal
+        );
+        assert_eq!(compare_kernel_versions("6.10.2", "6.9.12"), CmpOrdering::Greater);
+    }
+
+    #[test]
+    fn test_newest_kernel_version() {
+        let versions = [
+            String::from("6.1.0-18-amd64"),
+            String::from("6.1.0-21-amd64"),
+            String::from("6.1.0-25-rt-amd64"),
+        ];
+        assert_eq!(
+            newest_kernel_version("6.1.0-18-amd64", &versions),
+            Some("6.1.0-21-amd64")
+        );
+        assert_eq!(newest_kernel_version("6.1.0-21-amd64", &versions), None);
+        assert_eq!(newest_kernel_version("6.1.0-25-rt-amd64", &versions), None);
+    }
+
+    #[test]
+    fn test_arch_kernel_matches() {
+        assert!(arch_kernel_matches("6.7.4-arch1-1", "linux", "6.7.4.arch1-1"));
+        assert!(!arch_kernel_matches("6.7.4-arch1-1", "linux", "6.7.5.arch1-1"));
+        assert!(arch_kernel_matches("6.6.15-1-lts", "linux-lts", "6.6.15-1"));
+        assert!(arch_kernel_matches("6.7.4-zen1-1-zen", "linux-zen", "6.7.4.zen1-1"));
+    }
+
+    #[test]
+    fn test_pacdef_synced_nothing() {
+        assert!(pacdef_synced_nothing("nothing to do\n"));
+        assert!(!pacdef_synced_nothing(
+            "Would install the following packages:\n  [pacman]\n    ripgrep\n"
+        ));
+    }
+
+    #[test]
+    fn test_parse_pacdef_major_version() {
+        assert_eq!(parse_pacdef_major_version("pacdef 0.8.2\n"), Some(0));
+        assert_eq!(parse_pacdef_major_version("pacdef, version: 1.6.0\n"), Some(1));
+        assert_eq!(parse_pacdef_major_version("pacdef, version: 2.0.0-rc1\n"), Some(2));
+        assert_eq!(parse_pacdef_major_version(""), None);
+    }
+
+    #[test]
+    fn test_unknown_distribution() {
+        let os_release = Ini::load_from_str("ID=poky\nID_LIKE=\"yocto embedded\"\n").unwrap();
+        let error = Distribution::parse_os_release(&os_release).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TopgradeError>(),
+            Some(&TopgradeError::UnknownLinuxDistribution {
+                id: String::from("poky"),
+                id_like: String::from("yocto embedded"),
+            })
+        );
+    }
 }
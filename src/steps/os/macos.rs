@@ -1,78 +1,322 @@
 use crate::command::CommandExt;
+use crate::config::MacOSRestart;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
+use crate::report::SummaryNote;
 use crate::terminal::{print_separator, prompt_yesno};
 use crate::utils::{require_option, REQUIRE_SUDO};
 use crate::{utils::require, Step};
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use std::collections::HashSet;
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tracing::debug;
 
 pub fn run_macports(ctx: &ExecutionContext) -> Result<()> {
-    require("port")?;
+    let port = require("port")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    let yes = ctx.config().yes(Step::Macports);
+
+    // `port selfupdate` fails without the index of the ports, until the tree is synced once
+    if !macports_tree_synced(&port) {
+        return Err(SkipStep(String::from(
+            "The MacPorts ports tree has never been synced, run `sudo port -v selfupdate` once to set it up",
+        ))
+        .into());
+    }
 
     print_separator("MacPorts");
+
     ctx.run_type()
         .execute(sudo)
         .args(["port", "selfupdate"])
         .status_checked()?;
-    ctx.run_type()
-        .execute(sudo)
-        .args(["port", "-u", "upgrade", "outdated"])
-        .status_checked()?;
+
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg("port");
+    if yes {
+        command.arg("-N");
+    }
+    command.args(["-u", "upgrade", "outdated"]).status_checked()?;
+
     if ctx.config().cleanup() {
-        ctx.run_type()
-            .execute(sudo)
-            .args(["port", "-N", "reclaim"])
-            .status_checked()?;
+        // Removes the inactive ports and the distfiles that are no longer needed
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg("port");
+        if yes {
+            command.arg("-N");
+        }
+        command.args(["reclaim", "--disable-reminders"]).status_checked()?;
     }
 
     Ok(())
 }
 
+/// Whether any of the port sources has an index, assuming so when the sources can't be read.
+fn macports_tree_synced(port: &Path) -> bool {
+    // `port` is installed in `<prefix>/bin`
+    let Some(prefix) = port.parent().and_then(Path::parent) else {
+        return true;
+    };
+    let Ok(sources_conf) = fs::read_to_string(prefix.join("etc/macports/sources.conf")) else {
+        return true;
+    };
+
+    let indexes = macports_port_indexes(&sources_conf, prefix);
+    debug!("MacPorts port indexes: {indexes:?}");
+    indexes.is_empty() || indexes.iter().any(|index| index.exists())
+}
+
+/// The `PortIndex` files of the sources listed in `sources.conf`, created when they are synced.
+fn macports_port_indexes(sources_conf: &str, prefix: &Path) -> Vec<PathBuf> {
+    sources_conf
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|url| match url.strip_prefix("file://") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                // The other sources are synced to `<prefix>/var/macports/sources/<host>/<path>`
+                let path = url.split_once("://").map_or(url, |(_, path)| path);
+                prefix
+                    .join("var/macports/sources")
+                    .join(path.strip_suffix(".tar").unwrap_or(path))
+            }
+        })
+        .map(|directory| directory.join("PortIndex"))
+        .collect()
+}
+
 pub fn run_mas(ctx: &ExecutionContext) -> Result<()> {
     let mas = require("mas")?;
+
+    // `mas account` isn't supported since macOS 12, where it fails with another error while `mas upgrade` works
+    let account = Command::new(&mas).arg("account").output_checked_with_utf8(|_| Ok(()))?;
+    if !account.status.success() && (mas_not_signed_in(&account.stdout) || mas_not_signed_in(&account.stderr)) {
+        return Err(SkipStep(String::from("No Apple ID is signed in to the App Store")).into());
+    }
+
     print_separator("macOS App Store");
 
-    ctx.run_type().execute(mas).arg("upgrade").status_checked()
+    let excluded = ctx.config().mas_excluded_apps();
+    if excluded.is_empty() {
+        return ctx.run_type().execute(mas).arg("upgrade").status_checked();
+    }
+
+    let output = Command::new(&mas).arg("outdated").output_checked_utf8()?;
+    let mut failed = Vec::new();
+    for app in outdated_mas_apps(&output.stdout) {
+        if excluded.contains(&app) {
+            println!("Skipping excluded app {app}");
+            continue;
+        }
+        // A failing app doesn't keep the others from being upgraded
+        if let Err(e) = ctx
+            .run_type()
+            .execute(&mas)
+            .arg("upgrade")
+            .arg(app.to_string())
+            .status_checked()
+        {
+            debug!("Upgrading app {app} failed: {e:?}");
+            failed.push(app.to_string());
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to upgrade the apps {}", failed.join(", ")))
+    }
+}
+
+/// Whether `mas account` failed because no Apple ID is signed in.
+fn mas_not_signed_in(output: &str) -> bool {
+    output.contains("Not signed in")
+}
+
+/// Parse the app IDs from the output of `mas outdated`, whose lines look like
+/// `497799835 Xcode (15.3 -> 15.4)`.
+fn outdated_mas_apps(output: &str) -> Vec<u64> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse().ok())
+        .collect()
 }
 
 pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     print_separator("macOS system update");
 
+    println!("Finding available software");
+    let output = Command::new("softwareupdate").arg("--list").output_checked_utf8()?;
+    debug!("{:?}", output);
+
+    let updates = parse_software_updates(&output.stdout);
+    let install_os_upgrades = ctx.config().macos_install_os_upgrades();
+    let current_major = current_macos_major_version();
+    let selected = select_software_updates(
+        &updates,
+        ctx.config().macos_recommended_only(),
+        install_os_upgrades,
+        current_major,
+    );
+    if selected.is_empty() {
+        println!("No new software available.");
+        return Ok(());
+    }
+
+    for update in &selected {
+        println!("  {} ({})", update.title, update.label);
+    }
+
     let should_ask = !(ctx.config().yes(Step::System) || ctx.config().dry_run());
     if should_ask {
-        println!("Finding available software");
-        if system_update_available()? {
-            let answer = prompt_yesno("A system update is available. Do you wish to install it?")?;
-            if !answer {
-                return Ok(());
-            }
-            println!();
-        } else {
-            println!("No new software available.");
+        let answer = prompt_yesno("A system update is available. Do you wish to install it?")?;
+        if !answer {
             return Ok(());
         }
+        println!();
     }
 
+    let needs_restart = selected.iter().any(|update| update.restart);
+    let restart = needs_restart
+        && match ctx.config().macos_restart() {
+            MacOSRestart::Never => false,
+            MacOSRestart::Ask => !ctx.config().dry_run() && prompt_yesno("Restart after installing the updates?")?,
+            MacOSRestart::Auto => true,
+        };
+
     let mut command = ctx.run_type().execute("softwareupdate");
-    command.args(["--install", "--all"]);
+    command.arg("--install");
 
-    if should_ask {
-        command.arg("--no-scan");
+    // The labels are only listed when some of the updates are left out
+    let recommended = updates.iter().filter(|update| update.recommended).count();
+    if selected.len() == updates.len() {
+        command.arg("--all");
+    } else if ctx.config().macos_recommended_only() && selected.len() == recommended {
+        command.arg("--recommended");
+    } else {
+        command.args(selected.iter().map(|update| update.label.as_str()));
+    }
+
+    command.arg("--no-scan");
+
+    if restart {
+        command.arg("--restart");
+    }
+
+    command.status_checked()?;
+
+    if needs_restart && !restart && !ctx.run_type().dry() {
+        ctx.set_summary_note(
+            "macos-restart-required",
+            SummaryNote::Warning(String::from("The installed macOS updates require a restart")),
+        );
     }
 
-    command.status_checked()
+    Ok(())
 }
 
-fn system_update_available() -> Result<bool> {
-    let output = Command::new("softwareupdate").arg("--list").output_checked_utf8()?;
+/// An update listed by `softwareupdate --list`.
+#[derive(Debug, PartialEq, Eq)]
+struct SoftwareUpdate {
+    label: String,
+    title: String,
+    recommended: bool,
+    restart: bool,
+}
 
-    debug!("{:?}", output);
+/// Parses the updates listed by `softwareupdate --list`.
+///
+/// Since macOS Catalina each update is a `* Label: <label>` line followed by a
+/// `Title: <title>, Version: <version>, Size: <size>, Recommended: YES, Action: restart,` line.
+/// Older releases print `* <label>` followed by `<title> (<version>), <size> [recommended] [restart]`.
+fn parse_software_updates(output: &str) -> Vec<SoftwareUpdate> {
+    let mut updates = Vec::new();
+    let mut lines = output.lines().map(str::trim).peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(label) = line.strip_prefix("* ") else {
+            continue;
+        };
+        let label = label.strip_prefix("Label: ").unwrap_or(label).trim();
+        let details = lines.next_if(|line| !line.starts_with('*')).unwrap_or_default();
 
-    Ok(!output.stderr.contains("No new software available"))
+        let (title, recommended, restart) = match details.strip_prefix("Title: ") {
+            Some(details) => {
+                let fields: Vec<&str> = details
+                    .split(", ")
+                    .map(|field| field.trim().trim_end_matches(','))
+                    .collect();
+                (
+                    fields.first().copied().unwrap_or_default(),
+                    fields.contains(&"Recommended: YES"),
+                    fields
+                        .iter()
+                        .any(|field| field.trim_end_matches(',') == "Action: restart"),
+                )
+            }
+            None => (
+                details.split(" (").next().unwrap_or_default(),
+                details.contains("[recommended]"),
+                details.contains("[restart]"),
+            ),
+        };
+
+        updates.push(SoftwareUpdate {
+            label: label.to_string(),
+            title: title.to_string(),
+            recommended,
+            restart,
+        });
+    }
+
+    updates
+}
+
+/// Whether `update` upgrades macOS to a new major version, such as `macOS Sequoia 15.0-24A335` on macOS 14.
+///
+/// All the macOS updates are considered upgrades when the running version is unknown.
+fn is_os_upgrade(update: &SoftwareUpdate, current_major: Option<u32>) -> bool {
+    if !update.label.starts_with("macOS ") {
+        return false;
+    }
+
+    // The version follows the name of the release, `macOS Sonoma 14.4.1-23E224`
+    let major = update
+        .label
+        .split(|c: char| c == ' ' || c == '-')
+        .find_map(|word| word.split('.').next()?.parse::<u32>().ok());
+
+    match (major, current_major) {
+        (Some(major), Some(current_major)) => major > current_major,
+        _ => true,
+    }
+}
+
+/// Returns the updates to install according to the configuration.
+fn select_software_updates(
+    updates: &[SoftwareUpdate],
+    recommended_only: bool,
+    install_os_upgrades: bool,
+    current_major: Option<u32>,
+) -> Vec<&SoftwareUpdate> {
+    updates
+        .iter()
+        .filter(|update| !recommended_only || update.recommended)
+        .filter(|update| install_os_upgrades || !is_os_upgrade(update, current_major))
+        .collect()
+}
+
+/// The major version of the running macOS, 14 for macOS Sonoma.
+fn current_macos_major_version() -> Option<u32> {
+    Command::new("sw_vers")
+        .arg("-productVersion")
+        .output_checked_utf8()
+        .ok()
+        .and_then(|output| output.stdout.trim().split('.').next()?.parse().ok())
 }
 
 pub fn run_sparkle(ctx: &ExecutionContext) -> Result<()> {
@@ -95,6 +339,36 @@ pub fn run_sparkle(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// Updates the Command Line Tools with `softwareupdate`, Xcode itself is updated by the Xcodes step.
+pub fn update_xcode(ctx: &ExecutionContext) -> Result<()> {
+    // Prints the active developer directory, and fails without Xcode and the Command Line Tools
+    if Command::new("xcode-select").arg("-p").output_checked().is_err() {
+        return Err(SkipStep(String::from("Neither Xcode nor the Command Line Tools are installed")).into());
+    }
+
+    print_separator("Xcode Command Line Tools");
+
+    println!("Finding available software");
+    let output = Command::new("softwareupdate").arg("--list").output_checked_utf8()?;
+    let updates = parse_software_updates(&output.stdout);
+    let Some(update) = command_line_tools_update(&updates) else {
+        println!("The Command Line Tools are up to date");
+        return Ok(());
+    };
+
+    ctx.run_type()
+        .execute("softwareupdate")
+        .args(["--install", "--no-scan", &update.label])
+        .status_checked()
+}
+
+/// The pending update of the Command Line Tools.
+fn command_line_tools_update(updates: &[SoftwareUpdate]) -> Option<&SoftwareUpdate> {
+    updates
+        .iter()
+        .find(|update| update.label.starts_with("Command Line Tools"))
+}
+
 pub fn update_xcodes(ctx: &ExecutionContext) -> Result<()> {
     let xcodes = require("xcodes")?;
     print_separator("Xcodes");
@@ -224,18 +498,163 @@ pub fn process_xcodes_releases(releases_filtered: Vec<String>, should_ask: bool,
             "New Xcode release detected: {}",
             releases_filtered.last().cloned().unwrap_or_default()
         );
+        let install = if ctx.config().xcodes_install_latest() {
+            true
+        } else if should_ask {
+            prompt_yesno("Would you like to install it?")?
+        } else {
+            false
+        };
+        if install {
+            let _ = ctx
+                .run_type()
+                .execute(xcodes)
+                .args(["install", &releases_filtered.last().cloned().unwrap_or_default()])
+                .status_checked();
+        }
         if should_ask {
-            let answer_install = prompt_yesno("Would you like to install it?")?;
-            if answer_install {
-                let _ = ctx
-                    .run_type()
-                    .execute(xcodes)
-                    .args(["install", &releases_filtered.last().cloned().unwrap_or_default()])
-                    .status_checked();
-            }
             println!();
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATALINA_LIST: &str = "Software Update Tool\n\
+                                 \n\
+                                 Finding available software\n\
+                                 Software Update found the following new or updated software:\n\
+                                 * Label: Command Line Tools for Xcode-15.3\n\
+                                 \tTitle: Command Line Tools for Xcode, Version: 15.3, Size: 707501KiB, Recommended: YES, \n\
+                                 * Label: macOS Sonoma 14.4.1-23E224\n\
+                                 \tTitle: macOS Sonoma 14.4.1, Version: 14.4.1, Size: 1612173KiB, Recommended: YES, Action: restart, \n\
+                                 * Label: macOS Sequoia 15.0-24A335\n\
+                                 \tTitle: macOS Sequoia 15.0, Version: 15.0, Size: 6594099KiB, Recommended: NO, Action: restart, \n";
+
+    #[test]
+    fn test_macports_port_indexes() {
+        let sources_conf = "# MacPorts system-wide configuration file for ports tree sources.\n\
+                            \n\
+                            file:///Users/me/ports\n\
+                            rsync://rsync.macports.org/macports/release/tarballs/ports.tar [default]\n";
+        assert_eq!(
+            macports_port_indexes(sources_conf, Path::new("/opt/local")),
+            [
+                PathBuf::from("/Users/me/ports/PortIndex"),
+                PathBuf::from(
+                    "/opt/local/var/macports/sources/rsync.macports.org/macports/release/tarballs/ports/PortIndex"
+                ),
+            ]
+        );
+        assert!(macports_port_indexes("# No sources\n", Path::new("/opt/local")).is_empty());
+    }
+
+    #[test]
+    fn test_mas_not_signed_in() {
+        assert!(mas_not_signed_in("Error: Not signed in\n"));
+        assert!(!mas_not_signed_in(
+            "Error: This command is not supported on this macOS version due to changes in macOS. See: https://github.com/mas-cli/mas#known-issues\n"
+        ));
+    }
+
+    #[test]
+    fn test_outdated_mas_apps() {
+        let output = "497799835 Xcode      (15.3 -> 15.4)\n 1333542190 1Password 7 (7.9.10 -> 7.9.11)\n";
+        assert_eq!(outdated_mas_apps(output), vec![497799835, 1333542190]);
+        assert!(outdated_mas_apps("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_software_updates() {
+        assert_eq!(
+            parse_software_updates(CATALINA_LIST),
+            [
+                SoftwareUpdate {
+                    label: String::from("Command Line Tools for Xcode-15.3"),
+                    title: String::from("Command Line Tools for Xcode"),
+                    recommended: true,
+                    restart: false,
+                },
+                SoftwareUpdate {
+                    label: String::from("macOS Sonoma 14.4.1-23E224"),
+                    title: String::from("macOS Sonoma 14.4.1"),
+                    recommended: true,
+                    restart: true,
+                },
+                SoftwareUpdate {
+                    label: String::from("macOS Sequoia 15.0-24A335"),
+                    title: String::from("macOS Sequoia 15.0"),
+                    recommended: false,
+                    restart: true,
+                },
+            ]
+        );
+
+        let mojave = "Software Update Tool\n\
+                      \n\
+                      Finding available software\n\
+                      Software Update found the following new or updated software:\n   \
+                      * Safari12.1.1MojaveAuto-12.1.1\n\
+                      \tSafari (12.1.1), 67233K [recommended]\n   \
+                      * macOS 10.14.5 Update-\n\
+                      \tmacOS 10.14.5 Update ( ), 2541734K [recommended] [restart]\n";
+        assert_eq!(
+            parse_software_updates(mojave),
+            [
+                SoftwareUpdate {
+                    label: String::from("Safari12.1.1MojaveAuto-12.1.1"),
+                    title: String::from("Safari"),
+                    recommended: true,
+                    restart: false,
+                },
+                SoftwareUpdate {
+                    label: String::from("macOS 10.14.5 Update-"),
+                    title: String::from("macOS 10.14.5 Update"),
+                    recommended: true,
+                    restart: true,
+                },
+            ]
+        );
+
+        assert!(parse_software_updates("Software Update Tool\n\nFinding available software\n").is_empty());
+    }
+
+    #[test]
+    fn test_command_line_tools_update() {
+        let updates = parse_software_updates(CATALINA_LIST);
+        assert_eq!(
+            command_line_tools_update(&updates).map(|update| update.label.as_str()),
+            Some("Command Line Tools for Xcode-15.3")
+        );
+        assert_eq!(command_line_tools_update(&updates[1..]), None);
+    }
+
+    #[test]
+    fn test_select_software_updates() {
+        let updates = parse_software_updates(CATALINA_LIST);
+        let labels = |selected: Vec<&SoftwareUpdate>| -> Vec<String> {
+            selected.into_iter().map(|update| update.label.clone()).collect()
+        };
+
+        assert_eq!(
+            labels(select_software_updates(&updates, false, false, Some(14))),
+            ["Command Line Tools for Xcode-15.3", "macOS Sonoma 14.4.1-23E224"]
+        );
+        assert_eq!(
+            labels(select_software_updates(&updates, false, true, Some(14))).len(),
+            3
+        );
+        assert_eq!(
+            labels(select_software_updates(&updates, true, true, Some(14))),
+            ["Command Line Tools for Xcode-15.3", "macOS Sonoma 14.4.1-23E224"]
+        );
+        assert_eq!(
+            labels(select_software_updates(&updates, false, false, None)),
+            ["Command Line Tools for Xcode-15.3"]
+        );
+    }
+}
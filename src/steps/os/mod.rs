@@ -1,4 +1,4 @@
-#[cfg(target_os = "android")]
+#[cfg(any(target_os = "android", target_os = "linux"))]
 pub mod android;
 #[cfg(target_os = "linux")]
 mod archlinux;
@@ -6,10 +6,16 @@ mod archlinux;
 pub mod dragonfly;
 #[cfg(target_os = "freebsd")]
 pub mod freebsd;
+#[cfg(target_os = "haiku")]
+pub mod haiku;
+#[cfg(target_os = "illumos")]
+pub mod illumos;
 #[cfg(target_os = "linux")]
 pub mod linux;
 #[cfg(target_os = "macos")]
 pub mod macos;
+#[cfg(target_os = "netbsd")]
+pub mod netbsd;
 #[cfg(target_os = "openbsd")]
 pub mod openbsd;
 #[cfg(unix)]
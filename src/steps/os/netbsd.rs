@@ -0,0 +1,52 @@
+use crate::command::CommandExt;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::print_separator;
+use crate::utils::{require, require_option, which, REQUIRE_SUDO};
+use crate::Step;
+use color_eyre::eyre::Result;
+
+pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+
+    // Systems built from pkgsrc rebuild the outdated packages instead of installing binary ones
+    if ctx.config().netbsd_build_from_source() {
+        if let Some(pkg_rolling_replace) = which("pkg_rolling-replace") {
+            print_separator("NetBSD Packages (pkg_rolling-replace)");
+            return ctx
+                .run_type()
+                .execute(sudo)
+                .arg(pkg_rolling_replace)
+                .arg("-u")
+                .status_checked();
+        }
+        if let Some(pkg_chk) = which("pkg_chk") {
+            print_separator("NetBSD Packages (pkg_chk)");
+            return ctx.run_type().execute(sudo).arg(pkg_chk).arg("-u").status_checked();
+        }
+    }
+
+    let pkgin = require("pkgin")?;
+    let yes = ctx.config().yes(Step::Pkg);
+
+    print_separator("NetBSD Packages");
+
+    let pkgin_command = |command: &str| {
+        let mut executor = ctx.run_type().execute(sudo);
+        executor.arg(&pkgin);
+        if yes {
+            executor.arg("-y");
+        }
+        executor.arg(command);
+        executor
+    };
+
+    pkgin_command("update").status_checked()?;
+    pkgin_command("upgrade").status_checked()?;
+
+    if ctx.config().cleanup() {
+        pkgin_command("clean").status_checked()?;
+        pkgin_command("autoremove").status_checked()?;
+    }
+
+    Ok(())
+}
@@ -1,16 +1,61 @@
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 use crate::terminal::print_separator;
 use crate::utils::{require_option, REQUIRE_SUDO};
+use crate::Step;
 use color_eyre::eyre::Result;
+use std::process::Command;
 
-pub fn upgrade_openbsd(ctx: &ExecutionContext) -> Result<()> {
+/// Exit code of syspatch when there are no patches to install
+const SYSPATCH_NO_PATCHES: i32 = 2;
+
+pub fn run_syspatch(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
-    print_separator("OpenBSD Update");
+
+    let version = Command::new("sysctl")
+        .args(["-n", "kern.version"])
+        .output_checked_utf8()?;
+    if is_openbsd_snapshot(&version.stdout) {
+        return Err(SkipStep(String::from(
+            "syspatch only supports releases, update -current with `sysupgrade -s`",
+        ))
+        .into());
+    }
+
+    print_separator("OpenBSD Patches");
+
     ctx.run_type()
         .execute(sudo)
-        .args(["/usr/sbin/sysupgrade", "-n"])
-        .status_checked()
+        .arg("/usr/sbin/syspatch")
+        .status_checked_with(|status| {
+            if syspatch_succeeded(status.code()) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })
+}
+
+/// Whether syspatch succeeded, which includes finding no patches to install.
+fn syspatch_succeeded(code: Option<i32>) -> bool {
+    matches!(code, Some(0) | Some(SYSPATCH_NO_PATCHES))
+}
+
+/// Whether `kern.version` is the one of a -current or -beta snapshot, such as `OpenBSD 7.5-current (GENERIC.MP) #12`.
+fn is_openbsd_snapshot(kern_version: &str) -> bool {
+    kern_version
+        .split_whitespace()
+        .nth(1)
+        .map(|version| version.ends_with("-current") || version.ends_with("-beta"))
+        .unwrap_or(false)
+}
+
+pub fn run_fw_update(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
+    print_separator("OpenBSD Firmware");
+
+    ctx.run_type().execute(sudo).arg("/usr/sbin/fw_update").status_checked()
 }
 
 pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
@@ -24,10 +69,39 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
             .status_checked()?;
     }
 
-    ctx.run_type()
-        .execute(sudo)
-        .args(["/usr/sbin/pkg_add", "-u"])
-        .status_checked()?;
+    let mut command = ctx.run_type().execute(sudo);
+    command.args(["/usr/sbin/pkg_add", "-u"]);
+    // Take the default answer to the questions
+    if ctx.config().yes(Step::System) {
+        command.arg("-I");
+    }
+    command.status_checked()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn test_syspatch_succeeded() {
+        assert!(syspatch_succeeded(Some(0)));
+        assert!(syspatch_succeeded(Some(SYSPATCH_NO_PATCHES)));
+        assert!(!syspatch_succeeded(Some(1)));
+        // Killed by a signal
+        assert!(!syspatch_succeeded(None));
+    }
+
+    #[test]
+    fn test_is_openbsd_snapshot() {
+        assert!(is_openbsd_snapshot(
+            "OpenBSD 7.5-current (GENERIC.MP) #12: Sun Mar 10 12:34:56 MDT 2024\n    deraadt@amd64.openbsd.org:/usr/src/sys/arch/amd64/compile/GENERIC.MP\n"
+        ));
+        assert!(is_openbsd_snapshot(
+            "OpenBSD 7.6-beta (GENERIC.MP) #290: Mon Sep  2 10:00:00 MDT 2024\n"
+        ));
+        assert!(!is_openbsd_snapshot(
+            "OpenBSD 7.4 (GENERIC.MP) #1397: Tue Oct 10 09:02:37 MDT 2023\n"
+        ));
+        assert!(!is_openbsd_snapshot(""));
+    }
 }
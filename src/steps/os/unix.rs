@@ -1,3 +1,5 @@
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
@@ -7,16 +9,19 @@ use std::process::Command;
 use std::{env::var, path::Path};
 
 use crate::command::CommandExt;
-use crate::{Step, HOME_DIR};
+use crate::{Step, HOME_DIR, XDG_DIRS};
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
+use etcetera::base_strategy::BaseStrategy;
 use home;
 use ini::Ini;
 use tracing::debug;
 
 #[cfg(target_os = "linux")]
 use super::linux::Distribution;
+#[cfg(target_os = "macos")]
+use crate::config::BrewCaskUpdater;
 use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -24,59 +29,61 @@ use crate::executor::Executor;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use crate::executor::RunType;
 use crate::terminal::print_separator;
-use crate::utils::{require, require_option, PathExt, REQUIRE_SUDO};
+#[cfg(target_os = "macos")]
+use crate::terminal::print_warning;
+use crate::utils::{require, require_option, which, PathExt, REQUIRE_SUDO};
+
+/// The prefix of Homebrew on Intel Macs
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const INTEL_BREW_PREFIX: &str = "/usr/local";
 
+/// The prefix of Homebrew on Apple Silicon Macs
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-const INTEL_BREW: &str = "/usr/local/bin/brew";
+const ARM_BREW_PREFIX: &str = "/opt/homebrew";
 
+/// The prefix of Homebrew on Linux
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-const ARM_BREW: &str = "/opt/homebrew/bin/brew";
+const LINUX_BREW_PREFIX: &str = "/home/linuxbrew/.linuxbrew";
 
 #[derive(Copy, Clone, Debug)]
-#[allow(dead_code)]
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-pub enum BrewVariant {
+pub enum BrewVariant<'a> {
+    /// The `brew` found in `PATH`, when it doesn't belong to one of the prefixes
     Path,
-    MacIntel,
-    MacArm,
+    /// The `bin/brew` of a Homebrew prefix
+    Prefix(&'a Path),
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-impl BrewVariant {
-    fn binary_name(self) -> &'static str {
+impl BrewVariant<'_> {
+    fn binary_name(self) -> PathBuf {
         match self {
-            BrewVariant::Path => "brew",
-            BrewVariant::MacIntel => INTEL_BREW,
-            BrewVariant::MacArm => ARM_BREW,
+            BrewVariant::Path => PathBuf::from("brew"),
+            BrewVariant::Prefix(prefix) => prefix.join("bin/brew"),
         }
     }
 
-    #[cfg(target_os = "macos")]
-    fn is_path(&self) -> bool {
-        matches!(self, BrewVariant::Path)
-    }
-
-    fn both_both_exist() -> bool {
-        Path::new(INTEL_BREW).exists() && Path::new(ARM_BREW).exists()
-    }
-
-    pub fn step_title(self) -> &'static str {
-        let both_exists = Self::both_both_exist();
+    pub fn step_title(self) -> String {
         match self {
-            BrewVariant::MacArm if both_exists => "Brew (ARM)",
-            BrewVariant::MacIntel if both_exists => "Brew (Intel)",
-            _ => "Brew",
+            BrewVariant::Path => String::from("Brew"),
+            BrewVariant::Prefix(prefix) => format!("Brew ({})", prefix.display()),
         }
     }
 
     fn execute(self, run_type: RunType) -> Executor {
         match self {
-            BrewVariant::MacIntel if cfg!(target_arch = "aarch64") => {
+            // Run the brew of the other architecture through Rosetta, or natively on Apple Silicon
+            BrewVariant::Prefix(prefix)
+                if cfg!(all(target_os = "macos", target_arch = "aarch64"))
+                    && prefix == Path::new(INTEL_BREW_PREFIX) =>
+            {
                 let mut command = run_type.execute("arch");
                 command.arg("-x86_64").arg(self.binary_name());
                 command
             }
-            BrewVariant::MacArm if cfg!(target_arch = "x86_64") => {
+            BrewVariant::Prefix(prefix)
+                if cfg!(all(target_os = "macos", target_arch = "x86_64")) && prefix == Path::new(ARM_BREW_PREFIX) =>
+            {
                 let mut command = run_type.execute("arch");
                 command.arg("-arm64e").arg(self.binary_name());
                 command
@@ -85,9 +92,37 @@ impl BrewVariant {
         }
     }
 
-    #[cfg(target_os = "macos")]
-    fn is_macos_custom(binary_name: PathBuf) -> bool {
-        !(binary_name.as_os_str() == INTEL_BREW || binary_name.as_os_str() == ARM_BREW)
+    /// Skips the `brew` in `PATH` when it's already updated through its prefix.
+    fn skip_path_in_prefixes(self, ctx: &ExecutionContext, binary: &Path) -> Result<()> {
+        if !matches!(self, BrewVariant::Path) {
+            return Ok(());
+        }
+
+        let binary = binary.canonicalize().unwrap_or_else(|_| binary.to_path_buf());
+        let in_prefix = brew_prefixes(ctx).iter().any(|prefix| {
+            prefix
+                .join("bin/brew")
+                .canonicalize()
+                .is_ok_and(|prefix_binary| prefix_binary == binary)
+        });
+        if in_prefix {
+            return Err(SkipStep(format!("{} is updated through its prefix", binary.display())).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// The Homebrew prefixes to update, either configured or the known ones that exist.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn brew_prefixes(ctx: &ExecutionContext) -> Vec<PathBuf> {
+    match ctx.config().brew_prefixes() {
+        Some(prefixes) => prefixes.iter().map(PathBuf::from).collect(),
+        None => [ARM_BREW_PREFIX, INTEL_BREW_PREFIX, LINUX_BREW_PREFIX]
+            .iter()
+            .map(PathBuf::from)
+            .filter(|prefix| prefix.join("bin/brew").exists())
+            .collect(),
     }
 }
 
@@ -175,6 +210,7 @@ pub fn run_oh_my_fish(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(fish).args(["-c", "omf update"]).status_checked()
 }
 
+#[cfg(not(target_os = "netbsd"))]
 pub fn run_pkgin(ctx: &ExecutionContext) -> Result<()> {
     let pkgin = require("pkgin")?;
     let sudo = require_option(ctx.sudo().as_ref(), REQUIRE_SUDO.to_string())?;
@@ -271,21 +307,28 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
-    #[allow(unused_variables)]
     let binary_name = require(variant.binary_name())?;
-
-    #[cfg(target_os = "macos")]
-    {
-        if variant.is_path() && !BrewVariant::is_macos_custom(binary_name) {
-            return Err(SkipStep("Not a custom brew for macOS".to_string()).into());
-        }
-    }
+    variant.skip_path_in_prefixes(ctx, &binary_name)?;
 
     print_separator(variant.step_title());
     let run_type = ctx.run_type();
 
     variant.execute(run_type).arg("update").status_checked()?;
 
+    let list_versions = || {
+        variant
+            .execute(RunType::Wet)
+            .args(["list", "--formula", "--versions"])
+            .output_checked_utf8()
+            .map(|output| output.stdout)
+    };
+    let restart_services = ctx.config().brew_restart_services() && !run_type.dry();
+    let versions_before = if restart_services {
+        list_versions()?
+    } else {
+        String::new()
+    };
+
     let mut command = variant.execute(run_type);
     command.args(["upgrade", "--formula"]);
 
@@ -295,51 +338,224 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
 
     command.status_checked()?;
 
-    if ctx.config().cleanup() {
-        variant.execute(run_type).arg("cleanup").status_checked()?;
+    if restart_services {
+        let upgraded = upgraded_brew_formulae(&versions_before, &list_versions()?);
+        restart_brew_services(variant, run_type, &upgraded)?;
     }
 
-    if ctx.config().brew_autoremove() {
-        variant.execute(run_type).arg("autoremove").status_checked()?;
+    // Removing the unneeded dependencies first lets `brew cleanup` remove their downloads too
+    if ctx.config().cleanup() || ctx.config().brew_autoremove() {
+        run_brew_autoremove(variant, run_type)?;
+    }
+
+    if ctx.config().cleanup() {
+        variant.execute(run_type).arg("cleanup").status_checked()?;
     }
 
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
-    let binary_name = require(variant.binary_name())?;
-    if variant.is_path() && !BrewVariant::is_macos_custom(binary_name) {
-        return Err(SkipStep("Not a custom brew for macOS".to_string()).into());
+/// Restarts the started services of the `upgraded` formulae, so that they run the new version.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn restart_brew_services(variant: BrewVariant, run_type: RunType, upgraded: &[String]) -> Result<()> {
+    if upgraded.is_empty() {
+        return Ok(());
     }
-    print_separator(format!("{} - Cask", variant.step_title()));
-    let run_type = ctx.run_type();
 
-    let cask_upgrade_exists = variant
+    let services = variant
         .execute(RunType::Wet)
-        .args(["--repository", "buo/cask-upgrade"])
-        .output_checked_utf8()
-        .map(|p| Path::new(p.stdout.trim()).exists())?;
+        .args(["services", "list"])
+        .output_checked_utf8()?;
+    let started = started_brew_services(&services.stdout);
+
+    // A service failing to restart doesn't prevent restarting the others
+    let mut failed = Vec::new();
+    for service in upgraded.iter().filter(|formula| started.contains(formula)) {
+        println!("Restarting the {service} service...");
+        if let Err(e) = variant
+            .execute(run_type)
+            .args(["services", "restart", service])
+            .status_checked()
+        {
+            debug!("Failed to restart {service}: {e}");
+            failed.push(service.as_str());
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!("Failed to restart the services {}", failed.join(", ")))
+    }
+}
+
+/// Returns the formulae whose versions changed between two outputs of `brew list --versions`.
+///
+/// Formulae that were newly installed as dependencies aren't included.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn upgraded_brew_formulae(before: &str, after: &str) -> Vec<String> {
+    let parse = |output: &str| -> BTreeMap<String, String> {
+        output
+            .lines()
+            .filter_map(|line| line.trim().split_once(' '))
+            .map(|(name, versions)| (name.to_string(), versions.to_string()))
+            .collect()
+    };
+    let before = parse(before);
+
+    parse(after)
+        .into_iter()
+        .filter(|(name, versions)| before.get(name).is_some_and(|old| old != versions))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Returns the services listed as `started` by `brew services list`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn started_brew_services(output: &str) -> Vec<String> {
+    output
+        .lines()
+        // The first line is the header
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            (fields.next()? == "started").then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Runs `brew autoremove` and lists the removed formulae.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run_brew_autoremove(variant: BrewVariant, run_type: RunType) -> Result<()> {
+    let mut command = variant.execute(run_type);
+    command.arg("autoremove");
+    if run_type.dry() {
+        return command.status_checked();
+    }
+
+    let output = command.output_checked_utf8()?;
+    let removed = parse_brew_autoremove(&output.stdout);
+    if removed.is_empty() {
+        println!("No unneeded formulae to remove");
+    } else {
+        println!("Removed unneeded formulae: {}", removed.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Returns the formulae listed after `==> Autoremoving N unneeded formulae:` by `brew autoremove`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn parse_brew_autoremove(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("==> Autoremoving"))
+        .skip(1)
+        .take_while(|line| !line.is_empty() && !line.starts_with("==>") && !line.starts_with("Uninstalling"))
+        .map(|line| line.trim().to_string())
+        .collect()
+}
 
-    let mut brew_args = vec![];
+/// Whether `command` is listed by `brew commands --quiet`, which includes the external commands of the taps.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn has_brew_command(commands: &str, command: &str) -> bool {
+    commands.lines().any(|line| line.trim() == command)
+}
 
-    if cask_upgrade_exists {
-        brew_args.extend(["cu", "-y"]);
-        if ctx.config().brew_cask_greedy() {
-            brew_args.push("-a");
+/// The arguments upgrading the casks with `brew cu` from the `buo/cask-upgrade` tap, or with `brew upgrade --cask`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn cask_upgrade_args(use_cu: bool, yes: bool, cleanup: bool, greedy: bool, greedy_latest: bool) -> Vec<&'static str> {
+    let mut args = Vec::new();
+
+    if use_cu {
+        args.push("cu");
+        if yes {
+            args.push("-y");
+        }
+        if cleanup {
+            args.push("--cleanup");
+        }
+        if greedy {
+            args.push("-a");
         }
     } else {
-        brew_args.extend(["upgrade", "--cask"]);
-        if ctx.config().brew_cask_greedy() {
-            brew_args.push("--greedy");
+        args.extend(["upgrade", "--cask"]);
+        if greedy {
+            args.push("--greedy");
         }
-        if ctx.config().brew_greedy_latest() {
-            brew_args.push("--greedy-latest");
+        if greedy_latest {
+            args.push("--greedy-latest");
         }
     }
 
+    args
+}
+
+/// The casks of `greedy_casks` found in the output of `brew list --cask`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn installed_greedy_casks<'a>(installed: &str, greedy_casks: &'a [String]) -> Vec<&'a str> {
+    let installed: Vec<&str> = installed.lines().map(str::trim).collect();
+    greedy_casks
+        .iter()
+        .map(String::as_str)
+        .filter(|cask| installed.contains(cask))
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    let binary_name = require(variant.binary_name())?;
+    variant.skip_path_in_prefixes(ctx, &binary_name)?;
+
+    print_separator(format!("{} - Cask", variant.step_title()));
+    let run_type = ctx.run_type();
+
+    let use_cu = match ctx.config().brew_cask_updater() {
+        BrewCaskUpdater::Brew => false,
+        updater => {
+            let commands = variant
+                .execute(RunType::Wet)
+                .args(["commands", "--quiet"])
+                .output_checked_utf8()?;
+            let cu_exists = has_brew_command(&commands.stdout, "cu");
+            if updater == BrewCaskUpdater::Cu && !cu_exists {
+                print_warning("`brew cu` is not installed, upgrading the casks with `brew upgrade --cask`");
+            }
+            cu_exists
+        }
+    };
+
+    let brew_args = cask_upgrade_args(
+        use_cu,
+        ctx.config().yes(Step::BrewCask),
+        ctx.config().cleanup(),
+        ctx.config().brew_cask_greedy(),
+        ctx.config().brew_greedy_latest(),
+    );
+
     variant.execute(run_type).args(&brew_args).status_checked()?;
 
+    // Only these casks are upgraded even though they update themselves
+    let greedy_casks = ctx.config().brew_greedy_casks();
+    if !greedy_casks.is_empty() && !ctx.config().brew_cask_greedy() {
+        let installed = variant
+            .execute(RunType::Wet)
+            .args(["list", "--cask"])
+            .output_checked_utf8()?;
+        let casks = installed_greedy_casks(&installed.stdout, greedy_casks);
+        if !casks.is_empty() {
+            variant
+                .execute(run_type)
+                .args(["upgrade", "--cask", "--greedy"])
+                .args(casks)
+                .status_checked()?;
+        }
+    }
+
     if ctx.config().cleanup() {
         variant.execute(run_type).arg("cleanup").status_checked()?;
     }
@@ -352,17 +568,32 @@ pub fn run_guix(ctx: &ExecutionContext) -> Result<()> {
 
     let run_type = ctx.run_type();
 
-    let output = Command::new(&guix).arg("pull").output_checked_utf8();
-    debug!("guix pull output: {:?}", output);
-    let should_upgrade = output.is_ok();
-    debug!("Can Upgrade Guix: {:?}", should_upgrade);
-
     print_separator("Guix");
 
-    if should_upgrade {
-        return run_type.execute(&guix).args(["package", "-u"]).status_checked();
+    // On Guix System, the system step already pulled before reconfiguring
+    #[allow(unused_mut)]
+    let mut should_pull = true;
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(Distribution::Guix) = ctx.distribution() {
+            should_pull = !ctx.config().should_run(Step::System);
+        }
+    }
+
+    if should_pull {
+        run_type.execute(&guix).arg("pull").status_checked()?;
     }
-    Err(SkipStep(String::from("Guix Pull Failed, Skipping")).into())
+
+    run_type.execute(&guix).arg("upgrade").status_checked()?;
+
+    if ctx.config().cleanup() {
+        run_type
+            .execute(&guix)
+            .args(["gc", "--delete-generations=1m"])
+            .status_checked()?;
+    }
+
+    Ok(())
 }
 
 pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
@@ -393,22 +624,33 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
     run_type.execute(nix_channel).arg("--update").status_checked()?;
 
     if Path::new(&manifest_json_path).exists() {
+        // New-style profiles lock the flakes of their packages, upgrading updates those locks
         run_type
             .execute(nix)
             .args(nix_args())
             .arg("profile")
             .arg("upgrade")
-            .arg(".*")
+            .arg("--all")
             .arg("--verbose")
-            .status_checked()
+            .status_checked()?;
     } else {
         let mut command = run_type.execute(nix_env);
         command.arg("--upgrade");
         if let Some(args) = ctx.config().nix_env_arguments() {
             command.args(args.split_whitespace());
         };
-        command.status_checked()
+        command.status_checked()?;
+    }
+
+    if ctx.config().cleanup() {
+        let nix_collect_garbage = require("nix-collect-garbage")?;
+        run_type
+            .execute(nix_collect_garbage)
+            .args(["--delete-older-than", ctx.config().nix_gc_age()])
+            .status_checked()?;
     }
+
+    Ok(())
 }
 
 pub fn run_nix_self_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -421,7 +663,7 @@ pub fn run_nix_self_upgrade(ctx: &ExecutionContext) -> Result<()> {
     #[cfg(target_os = "linux")]
     {
         // We can't use `nix upgrade-nix` on NixOS.
-        if let Ok(Distribution::NixOS) = Distribution::detect() {
+        if let Ok(Distribution::NixOS) = ctx.distribution() {
             should_self_upgrade = false;
         }
     }
@@ -566,13 +808,45 @@ pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
 
 pub fn run_home_manager(ctx: &ExecutionContext) -> Result<()> {
     let home_manager = require("home-manager")?;
+    let flake = ctx.config().home_manager_flake();
+    let extra_args = ctx.config().home_manager();
+    let flake_in_args = extra_args.is_some_and(|args| args.iter().any(|arg| arg == "--flake"));
+
+    // Without a standalone configuration, home-manager is used as a NixOS or nix-darwin module,
+    // which gets updated along with the system
+    let config_dir = XDG_DIRS.config_dir();
+    if flake.is_none()
+        && !flake_in_args
+        && !config_dir.join("home-manager").exists()
+        && !config_dir.join("nixpkgs/home.nix").exists()
+    {
+        return Err(SkipStep(String::from("No standalone home-manager configuration found")).into());
+    }
 
     print_separator("home-manager");
 
+    if let Some(flake) = &flake {
+        let nix = require("nix")?;
+        ctx.run_type()
+            .execute(nix)
+            .args(["flake", "update"])
+            .current_dir(flake)
+            .status_checked()?;
+    } else if !flake_in_args {
+        if let Some(nix_channel) = which("nix-channel") {
+            ctx.run_type().execute(nix_channel).arg("--update").status_checked()?;
+        }
+    }
+
     let mut cmd = ctx.run_type().execute(home_manager);
     cmd.arg("switch");
 
-    if let Some(extra_args) = ctx.config().home_manager() {
+    // A flake in `home_manager_arguments` takes precedence, as `--flake` can't be passed twice
+    if let Some(flake) = flake.as_ref().filter(|_| !flake_in_args) {
+        cmd.args(["--flake", flake]);
+    }
+
+    if let Some(extra_args) = extra_args {
         cmd.args(extra_args);
     }
 
@@ -722,3 +996,67 @@ pub fn reboot() -> Result<()> {
     print!("Rebooting...");
     Command::new("sudo").arg("reboot").status_checked()
 }
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_brew_autoremove() {
+        let output = "==> Autoremoving 2 unneeded formulae:\n\
+                      libyaml\n\
+                      pcre2\n\
+                      Uninstalling /opt/homebrew/Cellar/libyaml/0.2.5... (11 files, 354.5KB)\n\
+                      Uninstalling /opt/homebrew/Cellar/pcre2/10.42... (230 files, 6.2MB)\n";
+        assert_eq!(parse_brew_autoremove(output), ["libyaml", "pcre2"]);
+        assert!(parse_brew_autoremove("").is_empty());
+    }
+
+    #[test]
+    fn test_upgraded_brew_formulae() {
+        let before = "postgresql@16 16.1_1\nredis 7.2.3\nwget 1.21.4\n";
+        let after = "libpq 16.2\npostgresql@16 16.1_1 16.2\nredis 7.2.4\nwget 1.21.4\n";
+        assert_eq!(upgraded_brew_formulae(before, after), ["postgresql@16", "redis"]);
+        assert!(upgraded_brew_formulae(before, before).is_empty());
+    }
+
+    #[test]
+    fn test_started_brew_services() {
+        let output = "Name          Status  User  File\n\
+                      postgresql@16 started alice ~/Library/LaunchAgents/homebrew.mxcl.postgresql@16.plist\n\
+                      redis         none\n\
+                      unbound       error   256   root ~/Library/LaunchAgents/homebrew.mxcl.unbound.plist\n";
+        assert_eq!(started_brew_services(output), ["postgresql@16"]);
+    }
+
+    #[test]
+    fn test_has_brew_command() {
+        let commands = "--cache\n--caskroom\naudit\ncu\ncleanup\n";
+        assert!(has_brew_command(commands, "cu"));
+        assert!(!has_brew_command("--cache\ncleanup\n", "cu"));
+    }
+
+    #[test]
+    fn test_cask_upgrade_args() {
+        assert_eq!(
+            cask_upgrade_args(true, true, true, false, false),
+            ["cu", "-y", "--cleanup"]
+        );
+        assert_eq!(cask_upgrade_args(true, false, false, true, true), ["cu", "-a"]);
+        assert_eq!(
+            cask_upgrade_args(false, true, true, true, true),
+            ["upgrade", "--cask", "--greedy", "--greedy-latest"]
+        );
+    }
+
+    #[test]
+    fn test_installed_greedy_casks() {
+        let greedy_casks = [String::from("microsoft-edge"), String::from("obs")];
+        assert_eq!(
+            installed_greedy_casks("firefox\nmicrosoft-edge\nvisual-studio-code\n", &greedy_casks),
+            ["microsoft-edge"]
+        );
+        assert!(installed_greedy_casks("", &greedy_casks).is_empty());
+    }
+}
@@ -1,39 +1,99 @@
-use std::path::Path;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::{ffi::OsStr, process::Command};
 
 use color_eyre::eyre::Result;
 use etcetera::base_strategy::BaseStrategy;
+use once_cell::sync::OnceCell;
 use tracing::debug;
 
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
+use crate::report::SummaryNote;
 use crate::terminal::{print_separator, print_warning};
-use crate::utils::{require, which};
+use crate::utils::{require, which, PathExt};
 use crate::{error::SkipStep, steps::git::RepoStep};
 use crate::{powershell, Step};
 
+/// The reasons of the reboot that was pending before running the Windows steps
+static PENDING_REBOOT: OnceCell<Vec<PendingReboot>> = OnceCell::new();
+
 pub fn run_chocolatey(ctx: &ExecutionContext) -> Result<()> {
     let choco = require("choco")?;
     let yes = ctx.config().yes(Step::Chocolatey);
 
     print_separator("Chocolatey");
 
-    let mut command = match ctx.sudo() {
-        Some(sudo) => {
-            let mut command = ctx.run_type().execute(sudo);
-            command.arg(choco);
-            command
-        }
-        None => ctx.run_type().execute(choco),
+    // choco skips the pinned packages on its own, list them so they aren't forgotten
+    let pins = Command::new(&choco)
+        .args(["pin", "list", "--limit-output"])
+        .output_checked_utf8()
+        .map(|output| parse_choco_pins(&output.stdout))
+        .unwrap_or_default();
+    if !pins.is_empty() {
+        println!("Pinned packages are not upgraded: {}", pins.join(", "));
+    }
+
+    let elevated = |ctx: &ExecutionContext| match ctx.sudo() {
+        Some(sudo) => sudo.execute(ctx, &choco),
+        None => ctx.run_type().execute(&choco),
     };
 
+    let mut command = elevated(ctx);
     command.args(["upgrade", "all"]);
 
     if yes {
         command.arg("--yes");
     }
 
-    command.status_checked()
+    if let Some(args) = ctx.config().choco_arguments() {
+        command.args(args.split_whitespace());
+    }
+
+    command.status_checked()?;
+
+    if ctx.config().cleanup() {
+        let version = Command::new(&choco).arg("--version").output_checked_utf8()?;
+        if parse_choco_major_version(&version.stdout)
+            .map(|major| major >= 2)
+            .unwrap_or(false)
+        {
+            let mut command = elevated(ctx);
+            command.args(["cache", "remove", "--expired"]);
+            if yes {
+                command.arg("--yes");
+            }
+            command.status_checked()?;
+        } else {
+            // Chocolatey 1.x has no cache command, its downloads are kept in the temporary directory
+            let cache = std::env::temp_dir().join("chocolatey");
+            if cache.exists() {
+                ctx.run_type()
+                    .execute("cmd")
+                    .args(["/C", "rmdir", "/S", "/Q"])
+                    .arg(cache)
+                    .status_checked()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the major version from the output of `choco --version`.
+fn parse_choco_major_version(output: &str) -> Option<u32> {
+    output.trim().split('.').next()?.parse().ok()
+}
+
+/// Returns the package names from the output of `choco pin list --limit-output`.
+fn parse_choco_pins(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split('|').next())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
 }
 
 pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
@@ -41,27 +101,295 @@ pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("winget");
 
+    let config = ctx.config();
+    let sources = config.winget_sources();
+    let sources: Vec<Option<&str>> = if sources.is_empty() {
+        vec![None]
+    } else {
+        sources.iter().map(|source| Some(source.as_str())).collect()
+    };
+
+    for source in sources {
+        ctx.run_type()
+            .execute(&winget)
+            .args(winget_upgrade_args(
+                source,
+                config.winget_include_unknown(),
+                config.winget_use_pinned(),
+                config.winget_arguments(),
+            ))
+            .status_checked()?;
+    }
+
+    if !ctx.run_type().dry() {
+        // Pinned packages, packages with an unknown version and the packages of the sources that
+        // are not in `winget_sources` are left outdated
+        let output = Command::new(&winget)
+            .args(["upgrade", "--include-unknown", "--include-pinned"])
+            .output_checked_with_utf8(|_| Ok(()))?;
+        note_winget_outdated(ctx, &parse_winget_table(&output.stdout));
+    }
+
+    Ok(())
+}
+
+/// The arguments of `winget upgrade --all`, restricted to `source` when set.
+fn winget_upgrade_args(
+    source: Option<&str>,
+    include_unknown: bool,
+    include_pinned: bool,
+    arguments: Option<&str>,
+) -> Vec<String> {
+    let mut args = vec![String::from("upgrade"), String::from("--all")];
+    if let Some(source) = source {
+        args.push(String::from("--source"));
+        args.push(source.to_string());
+    }
+    if include_unknown {
+        args.push(String::from("--include-unknown"));
+    }
+    if include_pinned {
+        args.push(String::from("--include-pinned"));
+    }
+    if let Some(arguments) = arguments {
+        args.extend(arguments.split_whitespace().map(String::from));
+    }
+    args
+}
+
+/// A row of the table printed by `winget upgrade`.
+#[derive(Debug, PartialEq, Eq)]
+struct WingetPackage {
+    name: String,
+    id: String,
+    version: String,
+    available: String,
+}
+
+/// Parses the table printed by `winget upgrade`, the column positions are taken from its header.
+fn parse_winget_table(output: &str) -> Vec<WingetPackage> {
+    // Progress spinners are overwritten with carriage returns
+    let lines: Vec<Vec<char>> = output
+        .lines()
+        .map(|line| line.rsplit('\r').next().unwrap_or_default().chars().collect())
+        .collect();
+
+    let Some(separator) = lines
+        .iter()
+        .position(|line| line.len() > 10 && line.iter().all(|c| *c == '-'))
+    else {
+        return Vec::new();
+    };
+    let Some(header) = separator.checked_sub(1).map(|i| &lines[i]) else {
+        return Vec::new();
+    };
+
+    let starts: Vec<usize> = (0..header.len())
+        .filter(|&i| header[i] != ' ' && (i == 0 || header[i - 1] == ' '))
+        .collect();
+    if starts.len() < 4 {
+        return Vec::new();
+    }
+
+    let column = |line: &[char], index: usize| -> String {
+        let start = starts[index].min(line.len());
+        let end = starts.get(index + 1).copied().unwrap_or(line.len()).min(line.len());
+        line[start..end].iter().collect::<String>().trim().to_string()
+    };
+
+    lines[separator + 1..]
+        .iter()
+        // The lines after the table don't line up with the columns
+        .take_while(|line| line.len() > starts[3] && starts[1..].iter().all(|&start| line.get(start - 1) == Some(&' ')))
+        .map(|line| WingetPackage {
+            name: column(line, 0),
+            id: column(line, 1),
+            version: column(line, 2),
+            available: column(line, 3),
+        })
+        .filter(|package| !package.id.is_empty())
+        .collect()
+}
+
+/// Lists the packages that winget left outdated in the summary.
+fn note_winget_outdated(ctx: &ExecutionContext, packages: &[WingetPackage]) {
+    if packages.is_empty() {
+        return;
+    }
+
+    let mut note = String::from("winget packages still outdated:");
+    for package in packages {
+        note.push_str(&format!(
+            "\n{} ({}): {} -> {}",
+            package.name, package.id, package.version, package.available
+        ));
+    }
+    ctx.set_summary_note("winget-outdated", SummaryNote::Info(note));
+}
+
+pub fn microsoft_store(ctx: &ExecutionContext) -> Result<()> {
+    skip_on_pending_reboot(ctx)?;
+
+    let powershell = powershell::Powershell::windows_powershell(ctx.config());
+
+    print_separator("Microsoft Store");
+
+    let Err(e) = powershell.microsoft_store(ctx) else {
+        return Ok(());
+    };
+
+    // The MDM bridge fails on many unmanaged machines, winget can update the Store apps instead
+    let winget = which("winget").filter(|_| ctx.config().microsoft_store_fallback_winget());
+    let Some(winget) = winget else {
+        return Err(e);
+    };
+
+    print_warning(format!("{e}, updating with winget instead"));
     ctx.run_type()
         .execute(winget)
-        .args(["upgrade", "--all"])
+        .args(["upgrade", "--source", "msstore", "--all", "--accept-package-agreements"])
         .status_checked()
 }
 
+pub fn run_office_update(ctx: &ExecutionContext) -> Result<()> {
+    let common_files = std::env::var_os("CommonProgramFiles")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("C:\\Program Files\\Common Files"));
+    let client = common_files
+        .join("microsoft shared\\ClickToRun\\OfficeC2RClient.exe")
+        .require()?;
+
+    print_separator("Microsoft Office");
+
+    ctx.run_type()
+        .execute(client)
+        .args(office_update_args(
+            ctx.config().office_display_level(),
+            ctx.config().office_force_app_shutdown(),
+        ))
+        .status_checked()?;
+
+    // The client only starts the update
+    println!("The Office update continues in the background");
+    Ok(())
+}
+
+fn office_update_args(display_level: bool, force_app_shutdown: bool) -> Vec<String> {
+    vec![
+        String::from("/update"),
+        String::from("user"),
+        format!("displaylevel={display_level}"),
+        format!("forceappshutdown={force_app_shutdown}"),
+    ]
+}
+
 pub fn run_scoop(ctx: &ExecutionContext) -> Result<()> {
     let scoop = require("scoop")?;
 
     print_separator("Scoop");
 
     ctx.run_type().execute(&scoop).args(["update"]).status_checked()?;
-    ctx.run_type().execute(&scoop).args(["update", "*"]).status_checked()?;
+
+    let mut command = ctx.run_type().execute(&scoop);
+    command.args(["update", "*"]);
+    if let Some(args) = ctx.config().scoop_arguments() {
+        command.args(args.split_whitespace());
+    }
+    command.status_checked()?;
 
     if ctx.config().cleanup() {
-        ctx.run_type().execute(&scoop).args(["cleanup", "*"]).status_checked()?;
+        ctx.run_type()
+            .execute(&scoop)
+            .args(["cleanup", "--all"])
+            .status_checked()?;
+        ctx.run_type()
+            .execute(&scoop)
+            .args(["cache", "rm", "*"])
+            .status_checked()?;
+    }
+
+    if !ctx.run_type().dry() {
+        // Held apps and apps that failed to update are left outdated
+        let output = Command::new(&scoop).arg("status").output_checked_utf8()?;
+        note_scoop_status(ctx, &parse_scoop_status(&output.stdout));
     }
 
     Ok(())
 }
 
+/// A row of the table printed by `scoop status`.
+#[derive(Debug, PartialEq, Eq)]
+struct ScoopApp {
+    name: String,
+    installed: String,
+    latest: String,
+    info: String,
+}
+
+/// Parses the table printed by `scoop status`.
+///
+/// The headers contain spaces, so the column positions are taken from the dashes under them.
+fn parse_scoop_status(output: &str) -> Vec<ScoopApp> {
+    let lines: Vec<Vec<char>> = output.lines().map(|line| line.trim_end().chars().collect()).collect();
+
+    let Some(separator) = lines
+        .iter()
+        .position(|line| line.first() == Some(&'-') && line.iter().all(|c| *c == '-' || *c == ' '))
+    else {
+        return Vec::new();
+    };
+    let Some(header) = separator.checked_sub(1).map(|i| &lines[i]) else {
+        return Vec::new();
+    };
+
+    let dashes = &lines[separator];
+    let starts: Vec<usize> = (0..dashes.len())
+        .filter(|&i| dashes[i] == '-' && (i == 0 || dashes[i - 1] == ' '))
+        .collect();
+
+    let column = |line: &[char], index: usize| -> String {
+        let start = starts[index].min(line.len());
+        let end = starts.get(index + 1).copied().unwrap_or(line.len()).min(line.len());
+        line[start..end].iter().collect::<String>().trim().to_string()
+    };
+    let find = |name: &str| (0..starts.len()).find(|&index| column(header, index).starts_with(name));
+    let (Some(name), Some(installed), Some(latest), Some(info)) =
+        (find("Name"), find("Installed"), find("Latest"), find("Info"))
+    else {
+        return Vec::new();
+    };
+
+    lines[separator + 1..]
+        .iter()
+        .take_while(|line| !line.is_empty())
+        .map(|line| ScoopApp {
+            name: column(line, name),
+            installed: column(line, installed),
+            latest: column(line, latest),
+            info: column(line, info),
+        })
+        .collect()
+}
+
+/// Lists the apps that scoop left outdated, such as held apps, in the summary.
+fn note_scoop_status(ctx: &ExecutionContext, apps: &[ScoopApp]) {
+    if apps.is_empty() {
+        return;
+    }
+
+    let mut note = String::from("Scoop apps not up to date:");
+    for app in apps {
+        note.push_str(&format!("\n{}", app.name));
+        if !app.latest.is_empty() {
+            note.push_str(&format!(": {} -> {}", app.installed, app.latest));
+        }
+        if !app.info.is_empty() {
+            note.push_str(&format!(" ({})", app.info));
+        }
+    }
+    ctx.set_summary_note("scoop-status", SummaryNote::Info(note));
+}
+
 pub fn update_wsl(ctx: &ExecutionContext) -> Result<()> {
     if !is_wsl_installed()? {
         return Err(SkipStep("WSL not installed".to_string()).into());
@@ -110,14 +438,140 @@ fn is_wsl_installed() -> Result<bool> {
 }
 
 fn get_wsl_distributions(wsl: &Path) -> Result<Vec<String>> {
-    let output = Command::new(wsl).args(["--list", "-q"]).output_checked_utf8()?.stdout;
-    Ok(output
+    let output = Command::new(wsl).args(["--list", "--quiet"]).output_checked()?.stdout;
+    Ok(decode_wsl_output(&output)
         .lines()
+        .map(str::trim)
         .filter(|s| !s.is_empty())
-        .map(|x| x.replace(['\u{0}', '\r'], ""))
+        .map(String::from)
+        .collect())
+}
+
+/// Decodes the output of `wsl.exe` itself, which is UTF-16LE unless `WSL_UTF8` is set.
+fn decode_wsl_output(output: &[u8]) -> String {
+    let output = output.strip_prefix(&[0xFF, 0xFE]).unwrap_or(output);
+    let is_utf16 =
+        output.chunks_exact(2).remainder().is_empty() && output.iter().skip(1).step_by(2).any(|&byte| byte == 0);
+    if is_utf16 {
+        let units: Vec<u16> = output
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(output).into_owned()
+    }
+}
+
+/// The package managers that can upgrade a WSL distribution, in the order they are probed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WslPackageManager {
+    Apt,
+    Dnf,
+    Zypper,
+    Pacman,
+    Apk,
+}
+
+impl WslPackageManager {
+    const ALL: [Self; 5] = [Self::Apt, Self::Dnf, Self::Zypper, Self::Pacman, Self::Apk];
+
+    fn binary(self) -> &'static str {
+        match self {
+            Self::Apt => "apt-get",
+            Self::Dnf => "dnf",
+            Self::Zypper => "zypper",
+            Self::Pacman => "pacman",
+            Self::Apk => "apk",
+        }
+    }
+
+    /// The shell command upgrading the distribution, run as root.
+    fn upgrade_command(self, yes: bool) -> String {
+        match (self, yes) {
+            (Self::Apt, true) => String::from("apt-get update && apt-get dist-upgrade -y"),
+            (Self::Apt, false) => String::from("apt-get update && apt-get dist-upgrade"),
+            (Self::Dnf, true) => String::from("dnf upgrade -y"),
+            (Self::Dnf, false) => String::from("dnf upgrade"),
+            (Self::Zypper, true) => String::from("zypper refresh && zypper --non-interactive update"),
+            (Self::Zypper, false) => String::from("zypper refresh && zypper update"),
+            (Self::Pacman, true) => String::from("pacman -Syu --noconfirm"),
+            (Self::Pacman, false) => String::from("pacman -Syu"),
+            (Self::Apk, _) => String::from("apk upgrade --update-cache"),
+        }
+    }
+}
+
+/// Returns the first package manager found by `command -v`, which prints one path per binary found.
+fn detect_wsl_package_manager(output: &str) -> Option<WslPackageManager> {
+    let found: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.trim().rsplit('/').next())
+        .collect();
+    WslPackageManager::ALL
+        .into_iter()
+        .find(|package_manager| found.contains(&package_manager.binary()))
+}
+
+/// Returns the installed WSL distributions, restricted by `wsl_distributions`.
+fn allowed_wsl_distributions(ctx: &ExecutionContext, wsl: &Path) -> Result<Vec<String>> {
+    let allowed = ctx.config().wsl_distributions();
+    let wsl_distributions = get_wsl_distributions(wsl)?;
+    debug!("WSL distributions: {:?}", wsl_distributions);
+
+    Ok(wsl_distributions
+        .into_iter()
+        .filter(|distribution| allowed.is_empty() || allowed.contains(distribution))
         .collect())
 }
 
+/// Returns the WSL distributions whose packages are upgraded from Windows.
+///
+/// The distributions with Topgrade installed are left to the WSL step, unless `wsl_run_topgrade` is disabled.
+pub fn wsl_package_distributions(ctx: &ExecutionContext) -> Result<Vec<String>> {
+    if !is_wsl_installed()? {
+        return Err(SkipStep("WSL not installed".to_string()).into());
+    }
+
+    let wsl = require("wsl")?;
+    Ok(allowed_wsl_distributions(ctx, &wsl)?
+        .into_iter()
+        .filter(|distribution| {
+            !(ctx.config().should_run(Step::Wsl) && ctx.config().wsl_run_topgrade())
+                || !has_wsl_topgrade(&wsl, distribution)
+        })
+        .collect())
+}
+
+fn has_wsl_topgrade(wsl: &Path, distribution: &str) -> bool {
+    Command::new(wsl)
+        .args(["-d", distribution, "bash", "-lc", "which topgrade"])
+        .output_checked_utf8()
+        .is_ok()
+}
+
+/// Upgrades the packages of a WSL distribution with the package manager found in it.
+pub fn upgrade_wsl_packages(ctx: &ExecutionContext, distribution: &str) -> Result<()> {
+    let wsl = require("wsl")?;
+
+    print_separator(format!("WSL ({distribution})"));
+
+    let binaries: Vec<&str> = WslPackageManager::ALL.iter().map(|pm| pm.binary()).collect();
+    let output = Command::new(&wsl)
+        .args(["-d", distribution, "-u", "root", "--", "sh", "-c"])
+        .arg(format!("command -v {}", binaries.join(" ")))
+        .output_checked_with_utf8(|_| Ok(()))?;
+    let package_manager = detect_wsl_package_manager(&output.stdout)
+        .ok_or_else(|| SkipStep(format!("No supported package manager found in {distribution}")))?;
+    debug!("{} uses {:?}", distribution, package_manager);
+
+    ctx.run_type()
+        .execute(&wsl)
+        .args(["-d", distribution, "-u", "root", "--", "sh", "-c"])
+        .arg(package_manager.upgrade_command(ctx.config().yes(Step::WslPackages)))
+        .status_checked()
+}
+
 fn upgrade_wsl_distribution(wsl: &Path, dist: &str, ctx: &ExecutionContext) -> Result<()> {
     let topgrade = Command::new(wsl)
         .args(["-d", dist, "bash", "-lc", "which topgrade"])
@@ -153,53 +607,47 @@ fn upgrade_wsl_distribution(wsl: &Path, dist: &str, ctx: &ExecutionContext) -> R
     // appends the next argument like so:
     // > `C:\WINDOWS\system32\wsl.EXE -d Ubuntu bash -c 'TOPGRADE_PREFIX=Ubuntu exec /bin/topgrade' -v`
     // which means `-v` isn't passed to `topgrade`.
-    let mut args = String::new();
+    let mut args = String::from("--no-retry --skip-notify");
     if ctx.config().verbose() {
-        args.push_str("-v");
+        args.push_str(" -v");
+    }
+    if ctx.config().yes(Step::Wsl) {
+        args.push_str(" -y");
     }
 
     command
         .args(["-d", dist, "bash", "-c"])
         .arg(format!("TOPGRADE_PREFIX={dist} exec {topgrade} {args}"));
 
-    if ctx.config().yes(Step::Wsl) {
-        command.arg("-y");
-    }
-
     command.status_checked()
 }
 
-pub fn run_wsl_topgrade(ctx: &ExecutionContext) -> Result<()> {
+/// Returns the WSL distributions with Topgrade installed, which are upgraded by running it.
+pub fn wsl_topgrade_distributions(ctx: &ExecutionContext) -> Result<Vec<String>> {
     if !is_wsl_installed()? {
         return Err(SkipStep("WSL not installed".to_string()).into());
     }
 
     let wsl = require("wsl")?;
-    let wsl_distributions = get_wsl_distributions(&wsl)?;
-    let mut ran = false;
-
-    debug!("WSL distributions: {:?}", wsl_distributions);
-
-    for distribution in wsl_distributions {
-        let result = upgrade_wsl_distribution(&wsl, &distribution, ctx);
-        debug!("Upgrading {:?}: {:?}", distribution, result);
-        if let Err(e) = result {
-            if e.is::<SkipStep>() {
-                continue;
-            }
-        }
-        ran = true
+    if !ctx.config().wsl_run_topgrade() {
+        return Ok(Vec::new());
     }
 
-    if ran {
-        Ok(())
-    } else {
-        Err(SkipStep(String::from("Could not find Topgrade in any WSL disribution")).into())
-    }
+    Ok(allowed_wsl_distributions(ctx, &wsl)?
+        .into_iter()
+        .filter(|distribution| has_wsl_topgrade(&wsl, distribution))
+        .collect())
+}
+
+pub fn run_wsl_topgrade(ctx: &ExecutionContext, distribution: &str) -> Result<()> {
+    let wsl = require("wsl")?;
+    upgrade_wsl_distribution(&wsl, distribution, ctx)
 }
 
 pub fn windows_update(ctx: &ExecutionContext) -> Result<()> {
-    let powershell = powershell::Powershell::windows_powershell();
+    skip_on_pending_reboot(ctx)?;
+
+    let powershell = powershell::Powershell::windows_powershell(ctx.config());
 
     print_separator("Windows Update");
 
@@ -214,6 +662,132 @@ pub fn windows_update(ctx: &ExecutionContext) -> Result<()> {
     }
 }
 
+/// Something that makes Windows wait for a reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PendingReboot {
+    ComponentServicing,
+    FileRenames,
+    WindowsUpdate,
+}
+
+impl Display for PendingReboot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PendingReboot::ComponentServicing => "component servicing",
+            PendingReboot::FileRenames => "pending file renames",
+            PendingReboot::WindowsUpdate => "Windows Update",
+        })
+    }
+}
+
+/// Whether the registry `key`, or its `value` when given, exists.
+fn registry_entry_exists(key: &str, value: Option<&str>) -> bool {
+    let mut command = Command::new("reg");
+    command.args(["query", key]);
+    if let Some(value) = value {
+        command.args(["/v", value]);
+    }
+    command.output_checked().is_ok()
+}
+
+/// Returns the reasons why Windows is waiting for a reboot.
+fn detect_pending_reboot(ctx: &ExecutionContext) -> Vec<PendingReboot> {
+    let mut reasons = Vec::new();
+
+    if registry_entry_exists(
+        "HKLM\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Component Based Servicing\\RebootPending",
+        None,
+    ) {
+        reasons.push(PendingReboot::ComponentServicing);
+    }
+
+    if registry_entry_exists(
+        "HKLM\\SYSTEM\\CurrentControlSet\\Control\\Session Manager",
+        Some("PendingFileRenameOperations"),
+    ) {
+        reasons.push(PendingReboot::FileRenames);
+    }
+
+    let powershell = powershell::Powershell::windows_powershell(ctx.config());
+    if powershell.supports_windows_update() && powershell.windows_update_reboot_required() {
+        reasons.push(PendingReboot::WindowsUpdate);
+    }
+
+    debug!("Pending reboot: {:?}", reasons);
+    reasons
+}
+
+/// Warns when a reboot is pending before running the Windows steps.
+pub fn check_pending_reboot(ctx: &ExecutionContext) {
+    let reasons = PENDING_REBOOT.get_or_init(|| detect_pending_reboot(ctx));
+    if !reasons.is_empty() {
+        print_warning(format!(
+            "A reboot is pending ({}), installing more updates may fail until then",
+            join_reasons(reasons)
+        ));
+    }
+}
+
+/// Skips the update steps when a reboot was pending and `abort_on_pending_reboot` is set.
+fn skip_on_pending_reboot(ctx: &ExecutionContext) -> Result<()> {
+    match PENDING_REBOOT.get() {
+        Some(reasons) if !reasons.is_empty() && ctx.config().abort_on_pending_reboot() => Err(SkipStep(format!(
+            "A reboot is pending ({}), reboot before installing more updates",
+            join_reasons(reasons)
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Recommends a reboot when one is pending at the end of the run.
+pub fn show_pending_reboot(ctx: &ExecutionContext) {
+    let reasons = detect_pending_reboot(ctx);
+    if !reasons.is_empty() {
+        print_warning(format!(
+            "\nA reboot is pending ({}), reboot recommended",
+            join_reasons(&reasons)
+        ));
+    }
+}
+
+fn join_reasons(reasons: &[PendingReboot]) -> String {
+    reasons.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Exit code of DISM when the operation succeeded but needs a reboot to complete
+const DISM_REBOOT_REQUIRED: i32 = 3010;
+
+pub fn run_dism_cleanup(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().cleanup() {
+        return Err(SkipStep(String::from("Cleanup is disabled")).into());
+    }
+
+    let dism = require("dism")?;
+
+    print_separator("Component store cleanup");
+
+    let mut command = match ctx.sudo() {
+        Some(sudo) => sudo.execute(ctx, &dism),
+        None => ctx.run_type().execute(&dism),
+    };
+    command.args(dism_cleanup_args(ctx.config().windows_resetbase()));
+
+    if command.status_code_checked_with_codes(&[DISM_REBOOT_REQUIRED])? == Some(DISM_REBOOT_REQUIRED) {
+        print_warning("A reboot is required to complete the component store cleanup");
+    }
+
+    Ok(())
+}
+
+fn dism_cleanup_args(reset_base: bool) -> Vec<&'static str> {
+    let mut args = vec!["/Online", "/Cleanup-Image", "/StartComponentCleanup"];
+    if reset_base {
+        args.push("/ResetBase");
+    }
+    args
+}
+
 pub fn reboot() -> Result<()> {
     // If this works, it won't return, but if it doesn't work, it may return a useful error
     // message.
@@ -238,3 +812,164 @@ pub fn insert_startup_scripts(git_repos: &mut RepoStep) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scoop_status() {
+        let output = include_str!("scoop/status");
+        assert_eq!(
+            parse_scoop_status(output),
+            [
+                ScoopApp {
+                    name: String::from("7zip"),
+                    installed: String::from("23.01"),
+                    latest: String::from("24.08"),
+                    info: String::new(),
+                },
+                ScoopApp {
+                    name: String::from("nodejs"),
+                    installed: String::from("20.9.0"),
+                    latest: String::from("21.1.0"),
+                    info: String::from("Held package"),
+                },
+                ScoopApp {
+                    name: String::from("vlc"),
+                    installed: String::from("3.0.18"),
+                    latest: String::from("3.0.20"),
+                    info: String::from("Install failed"),
+                },
+                ScoopApp {
+                    name: String::from("git"),
+                    installed: String::from("2.42.0.windows.2"),
+                    latest: String::new(),
+                    info: String::from("Manifest removed"),
+                },
+            ]
+        );
+        assert!(parse_scoop_status("Scoop is up to date.\r\nEverything is ok!\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_join_reasons() {
+        assert_eq!(
+            join_reasons(&[PendingReboot::ComponentServicing, PendingReboot::WindowsUpdate]),
+            "component servicing, Windows Update"
+        );
+        assert_eq!(join_reasons(&[]), "");
+    }
+
+    #[test]
+    fn test_dism_cleanup_args() {
+        assert_eq!(
+            dism_cleanup_args(false),
+            ["/Online", "/Cleanup-Image", "/StartComponentCleanup"]
+        );
+        assert_eq!(
+            dism_cleanup_args(true),
+            ["/Online", "/Cleanup-Image", "/StartComponentCleanup", "/ResetBase"]
+        );
+    }
+
+    #[test]
+    fn test_office_update_args() {
+        assert_eq!(
+            office_update_args(false, false),
+            ["/update", "user", "displaylevel=false", "forceappshutdown=false"]
+        );
+        assert_eq!(
+            office_update_args(true, true),
+            ["/update", "user", "displaylevel=true", "forceappshutdown=true"]
+        );
+    }
+
+    #[test]
+    fn test_decode_wsl_output() {
+        let utf16: Vec<u8> = "Ubuntu-22.04\r\nDébian\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(decode_wsl_output(&utf16), "Ubuntu-22.04\r\nDébian\r\n");
+        assert_eq!(decode_wsl_output("Ubuntu\n".as_bytes()), "Ubuntu\n");
+    }
+
+    #[test]
+    fn test_detect_wsl_package_manager() {
+        assert_eq!(
+            detect_wsl_package_manager("/usr/bin/apt-get\n"),
+            Some(WslPackageManager::Apt)
+        );
+        assert_eq!(
+            detect_wsl_package_manager("/usr/bin/dnf\n/usr/bin/zypper\n"),
+            Some(WslPackageManager::Dnf)
+        );
+        assert_eq!(detect_wsl_package_manager("/sbin/apk\n"), Some(WslPackageManager::Apk));
+        assert_eq!(detect_wsl_package_manager(""), None);
+    }
+
+    #[test]
+    fn test_parse_choco_major_version() {
+        assert_eq!(parse_choco_major_version("2.2.2\r\n"), Some(2));
+        assert_eq!(parse_choco_major_version("1.4.0\r\n"), Some(1));
+        assert_eq!(parse_choco_major_version("2.3.0-beta-20240122\r\n"), Some(2));
+        assert_eq!(parse_choco_major_version(""), None);
+    }
+
+    #[test]
+    fn test_parse_choco_pins() {
+        assert_eq!(
+            parse_choco_pins("firefox|121.0.1\r\nnodejs-lts|18.19.0\r\n"),
+            ["firefox", "nodejs-lts"]
+        );
+        assert!(parse_choco_pins("").is_empty());
+    }
+
+    #[test]
+    fn test_winget_upgrade_args() {
+        assert_eq!(winget_upgrade_args(None, false, false, None), ["upgrade", "--all"]);
+        assert_eq!(
+            winget_upgrade_args(Some("winget"), true, true, Some("--silent --disable-interactivity")),
+            [
+                "upgrade",
+                "--all",
+                "--source",
+                "winget",
+                "--include-unknown",
+                "--include-pinned",
+                "--silent",
+                "--disable-interactivity"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_winget_table() {
+        let output = include_str!("winget/upgrade");
+        assert_eq!(
+            parse_winget_table(output),
+            [
+                WingetPackage {
+                    name: String::from("Microsoft Edge"),
+                    id: String::from("Microsoft.Edge"),
+                    version: String::from("Unknown"),
+                    available: String::from("120.0.2210.91"),
+                },
+                WingetPackage {
+                    name: String::from("Git"),
+                    id: String::from("Git.Git"),
+                    version: String::from("2.42.0.2"),
+                    available: String::from("2.43.0"),
+                },
+                WingetPackage {
+                    name: String::from("Microsoft Visual C++ 2015-2022 Redistr…"),
+                    id: String::from("Microsoft.VCRedist.2015+.x64"),
+                    version: String::from("14.36.32532.0"),
+                    available: String::from("14.38.33130.0"),
+                },
+            ]
+        );
+        assert!(parse_winget_table("No installed package found matching input criteria.\r\n").is_empty());
+    }
+}
@@ -1,12 +1,25 @@
-#[cfg(windows)]
-use std::path::Path;
-use std::path::PathBuf;
+use std::fmt::{self, Display, Formatter};
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use color_eyre::eyre::Result;
+#[cfg(windows)]
+use chrono::Local;
+use chrono::{NaiveTime, Timelike};
+use color_eyre::eyre::{eyre, Result};
+use tracing::debug;
+use wildmatch::WildMatch;
 
 use crate::command::CommandExt;
+use crate::config::{Config, PsResourceGet, UpdatesAutoReboot};
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
+#[cfg(windows)]
+use crate::executor::Executor;
+#[cfg(windows)]
+use crate::report::SummaryNote;
+#[cfg(windows)]
+use crate::terminal::prompt_yesno;
 use crate::terminal::{is_dumb, print_separator};
 use crate::utils::{require_option, which, PathExt};
 use crate::Step;
@@ -14,6 +27,8 @@ use crate::Step;
 pub struct Powershell {
     path: Option<PathBuf>,
     profile: Option<PathBuf>,
+    /// Pass `-ExecutionPolicy Bypass`, which only applies to the launched process.
+    execution_policy_bypass: bool,
 }
 
 impl Powershell {
@@ -21,42 +36,56 @@ impl Powershell {
     ///
     /// If the powershell binary is not found, or the current terminal is dumb
     /// then the instance of this struct will skip all the powershell steps.
-    pub fn new() -> Self {
-        let path = which("pwsh").or_else(|| which("powershell")).filter(|_| !is_dumb());
+    pub fn new(config: &Config) -> Self {
+        let mut powershell = Powershell {
+            path: which("pwsh").or_else(|| which("powershell")).filter(|_| !is_dumb()),
+            profile: None,
+            execution_policy_bypass: config.powershell_execution_policy_bypass(),
+        };
 
-        let profile = path.as_ref().and_then(|path| {
+        powershell.profile = powershell.path.as_ref().and_then(|path| {
             Command::new(path)
-                .args(["-NoProfile", "-Command", "Split-Path $profile"])
+                .args(powershell.args("Split-Path $profile"))
                 .output_checked_utf8()
                 .map(|output| PathBuf::from(output.stdout.trim()))
                 .and_then(|p| p.require())
                 .ok()
         });
 
-        Powershell { path, profile }
+        powershell
     }
 
     #[cfg(windows)]
-    pub fn windows_powershell() -> Self {
+    pub fn windows_powershell(config: &Config) -> Self {
         Powershell {
             path: which("powershell").filter(|_| !is_dumb()),
             profile: None,
+            execution_policy_bypass: config.powershell_execution_policy_bypass(),
         }
     }
 
-    #[cfg(windows)]
-    pub fn has_module(powershell: &Path, command: &str) -> bool {
+    /// The arguments running `command` without loading the profile.
+    fn args<'a>(&self, command: &'a str) -> Vec<&'a str> {
+        powershell_args(self.execution_policy_bypass, command)
+    }
+
+    pub fn has_module(&self, powershell: &Path, command: &str) -> bool {
         Command::new(powershell)
-            .args([
-                "-NoProfile",
-                "-Command",
-                &format!("Get-Module -ListAvailable {command}"),
-            ])
+            .args(self.args(&format!("Get-Module -ListAvailable {command}")))
             .output_checked_utf8()
             .map(|result| !result.stdout.is_empty())
             .unwrap_or(false)
     }
 
+    /// The major version of `powershell`, 5 for Windows PowerShell.
+    fn major_version(&self, powershell: &Path) -> Option<u32> {
+        Command::new(powershell)
+            .args(self.args("$PSVersionTable.PSVersion.Major"))
+            .output_checked_utf8()
+            .ok()
+            .and_then(|output| output.stdout.trim().parse().ok())
+    }
+
     pub fn profile(&self) -> Option<&PathBuf> {
         self.profile.as_ref()
     }
@@ -66,29 +95,145 @@ impl Powershell {
 
         print_separator("Powershell Modules Update");
 
-        let mut cmd = vec!["Update-Module"];
+        let use_psresourceget = match ctx.config().powershell_use_psresourceget() {
+            PsResourceGet::Auto => self.has_module(powershell, "Microsoft.PowerShell.PSResourceGet"),
+            PsResourceGet::Always => true,
+            PsResourceGet::Never => false,
+        };
+        let verbose = ctx.config().verbose();
+        let force = ctx.config().yes(Step::Powershell);
 
-        if ctx.config().verbose() {
-            cmd.push("-Verbose")
+        let exclusions = ctx.config().powershell_module_exclusions();
+        let throttle_limit = ctx.config().powershell_parallel();
+        // `ForEach-Object -Parallel` was added in pwsh 7.0
+        let parallel = throttle_limit > 1 && self.major_version(powershell).is_some_and(|major| major >= 7);
+        if exclusions.is_empty() && !parallel {
+            println!("Updating modules...");
+            return ctx
+                .run_type()
+                .execute(powershell)
+                // This probably doesn't need `shell_words::join`.
+                .args(self.args(&update_modules_command(use_psresourceget, None, verbose, force)))
+                .status_checked();
         }
 
-        if ctx.config().yes(Step::Powershell) {
-            cmd.push("-Force")
+        let list_command = if use_psresourceget {
+            "Get-InstalledPSResource | Select-Object -ExpandProperty Name -Unique"
+        } else {
+            "Get-InstalledModule | Select-Object -ExpandProperty Name"
+        };
+        let installed = Command::new(powershell)
+            .args(self.args(list_command))
+            .output_checked_utf8()?;
+        let modules = included_modules(&installed.stdout, exclusions);
+
+        if parallel {
+            println!("Updating {} modules, {throttle_limit} at a time...", modules.len());
+            return ctx
+                .run_type()
+                .execute(powershell)
+                .args(self.args(&parallel_update_modules_script(
+                    &modules,
+                    throttle_limit,
+                    use_psresourceget,
+                    verbose,
+                    force,
+                )))
+                .status_checked();
         }
 
-        println!("Updating modules...");
+        // Each module is updated on its own, so that a failing module doesn't prevent updating the others
+        let mut failed = Vec::new();
+        for module in modules {
+            println!("Updating {module}...");
+            if let Err(e) = ctx
+                .run_type()
+                .execute(powershell)
+                .args(self.args(&update_modules_command(
+                    use_psresourceget,
+                    Some(&module),
+                    verbose,
+                    force,
+                )))
+                .status_checked()
+            {
+                debug!("Failed to update {module}: {e}");
+                failed.push(module);
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!("Failed to update the modules {}", failed.join(", ")))
+        }
+    }
+
+    /// Runs one of the `powershell_custom_commands`.
+    pub fn run_custom_command(&self, ctx: &ExecutionContext, command: &str) -> Result<()> {
+        let powershell = require_option(self.path.as_ref(), String::from("Powershell is not installed"))?;
+
+        print_separator(format!("Powershell ({command})"));
+
         ctx.run_type()
             .execute(powershell)
-            // This probably doesn't need `shell_words::join`.
-            .args(["-NoProfile", "-Command", &cmd.join(" ")])
+            .args(self.args(command))
             .status_checked()
     }
 
+    /// Runs `Update-Help`, and on Windows also updates the help of Windows PowerShell as administrator.
+    ///
+    /// Some modules always fail to update their help, so those errors are ignored and the command always
+    /// succeeds once PowerShell itself ran.
+    pub fn update_help(&self, ctx: &ExecutionContext) -> Result<()> {
+        let powershell = require_option(self.path.as_ref(), String::from("Powershell is not installed"))?;
+
+        // The help files are downloaded from this host
+        if ("go.microsoft.com", 443).to_socket_addrs().is_err() {
+            return Err(SkipStep(String::from("The help can't be updated while offline")).into());
+        }
+
+        print_separator("Powershell Help Update");
+
+        // `SilentlyContinue` still leaves `$?` false after a failed module, which would fail the step
+        let command = "Update-Help -Force -ErrorAction SilentlyContinue; exit 0";
+        ctx.run_type()
+            .execute(powershell)
+            .args(self.args(command))
+            .status_checked()?;
+
+        #[cfg(windows)]
+        {
+            if let Some(windows_powershell) = which("powershell").filter(|path| path != powershell) {
+                elevated_command(ctx, &windows_powershell)
+                    .args(self.args(command))
+                    .status_checked()?;
+            }
+        }
+
+        Ok(())
+    }
+
     #[cfg(windows)]
     pub fn supports_windows_update(&self) -> bool {
         self.path
             .as_ref()
-            .map(|p| Self::has_module(p, "PSWindowsUpdate"))
+            .map(|p| self.has_module(p, "PSWindowsUpdate"))
+            .unwrap_or(false)
+    }
+
+    /// Whether PSWindowsUpdate reports that a reboot is required.
+    #[cfg(windows)]
+    pub fn windows_update_reboot_required(&self) -> bool {
+        self.path
+            .as_ref()
+            .and_then(|powershell| {
+                Command::new(powershell)
+                    .args(self.args("Import-Module PSWindowsUpdate; Get-WURebootStatus -Silent"))
+                    .output_checked_utf8()
+                    .ok()
+            })
+            .map(|output| output.stdout.trim().eq_ignore_ascii_case("true"))
             .unwrap_or(false)
     }
 
@@ -98,27 +243,426 @@ impl Powershell {
 
         debug_assert!(self.supports_windows_update());
 
-        let mut command = if let Some(sudo) = ctx.sudo() {
-            let mut command = ctx.run_type().execute(sudo);
-            command.arg(powershell);
-            command
+        let filters = windows_update_filters(
+            ctx.config().windows_update_exclude_drivers(),
+            ctx.config().windows_update_excluded_kbs(),
+        );
+
+        // A dry run only shows the install command, without scanning for the updates
+        if !ctx.config().dry_run() {
+            let list = Command::new(powershell)
+                .args(self.args(&format!(
+                    "Import-Module PSWindowsUpdate; Get-WindowsUpdate -MicrosoftUpdate {filters} | \
+                     ForEach-Object {{ \"$($_.KB)`t$($_.Size)`t$($_.Title)\" }}"
+                )))
+                .output_checked_utf8()?;
+            let updates = parse_windows_updates(&list.stdout);
+            if updates.is_empty() {
+                println!("No updates available");
+                return Ok(());
+            }
+
+            println!("{} updates available:", updates.len());
+            for update in &updates {
+                println!("  {update}");
+            }
+
+            let should_ask = !(ctx.config().accept_all_windows_updates() || ctx.config().yes(Step::System));
+            if should_ask && !prompt_yesno("Install these updates?")? {
+                return Err(SkipStep(String::from("The updates were not confirmed")).into());
+            }
+
+            let mut note = String::from("Windows updates:");
+            for update in &updates {
+                note.push_str(&format!("\n{update}"));
+            }
+            ctx.set_summary_note("windows-updates", SummaryNote::Info(note));
+        }
+
+        let mut command = elevated_command(ctx, powershell);
+
+        let bypass = if self.execution_policy_bypass {
+            "-ExecutionPolicy Bypass "
         } else {
-            ctx.run_type().execute(powershell)
+            ""
         };
-
+        let reboot = windows_update_reboot_argument(ctx.config().windows_updates_auto_reboot(), Local::now().time());
+        let install = install_windows_update_command(&reboot, &filters);
         command
-            .args([
+            .args(self.args(&format!(
+                "Start-Process powershell -Verb runAs -ArgumentList '{bypass}Import-Module PSWindowsUpdate; {install}'"
+            )))
+            .status_checked()
+    }
+
+    /// Starts updating the Microsoft Store apps in the background through the MDM bridge.
+    ///
+    /// Returns an error describing the failure when the scan method doesn't succeed.
+    #[cfg(windows)]
+    pub fn microsoft_store(&self, ctx: &ExecutionContext) -> Result<()> {
+        let powershell = require_option(self.path.as_ref(), String::from("Powershell is not installed"))?;
+
+        let mut command = elevated_command(ctx, powershell);
+
+        println!("Scanning for updates...");
+        command.args(self.args(
+            "(Get-CimInstance -Namespace \"Root\\cimv2\\mdm\\dmmap\" \
+             -ClassName \"MDM_EnterpriseModernAppManagement_AppManagement01\" | \
+             Invoke-CimMethod -MethodName UpdateScanMethod).ReturnValue",
+        ));
+
+        if ctx.run_type().dry() {
+            return command.status_checked();
+        }
+
+        let output = command.output_checked_with_utf8(|_| Ok(()))?;
+        parse_store_scan_result(&output.stdout).map_err(|message| eyre!(message))?;
+        println!("Success, Microsoft Store apps are being updated in the background");
+        Ok(())
+    }
+}
+
+/// Runs `powershell` as administrator, or as the current user when no sudo is available.
+#[cfg(windows)]
+fn elevated_command(ctx: &ExecutionContext, powershell: &Path) -> Executor {
+    match ctx.sudo() {
+        Some(sudo) => sudo.execute(ctx, powershell),
+        None => ctx.run_type().execute(powershell),
+    }
+}
+
+/// The arguments running `command` without loading the profile, optionally bypassing the execution policy.
+fn powershell_args(execution_policy_bypass: bool, command: &str) -> Vec<&str> {
+    let mut args = vec!["-NoProfile"];
+    if execution_policy_bypass {
+        args.extend(["-ExecutionPolicy", "Bypass"]);
+    }
+    args.extend(["-Command", command]);
+    args
+}
+
+/// The PSWindowsUpdate arguments skipping the excluded updates.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn windows_update_filters(exclude_drivers: bool, excluded_kbs: &[String]) -> String {
+    let mut filters = Vec::new();
+
+    if exclude_drivers {
+        filters.push(String::from("-NotCategory \"Drivers\""));
+    }
+
+    if !excluded_kbs.is_empty() {
+        filters.push(format!("-NotKBArticleID {}", excluded_kbs.join(",")));
+    }
+
+    filters.join(" ")
+}
+
+/// The PSWindowsUpdate argument handling the reboot required by the updates, given the current time.
+///
+/// The command is wrapped in single quotes by `Powershell::windows_update`, so it must not contain any.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn windows_update_reboot_argument(reboot: UpdatesAutoReboot, now: NaiveTime) -> String {
+    match reboot {
+        UpdatesAutoReboot::Yes => String::from("-AutoReboot"),
+        UpdatesAutoReboot::No => String::from("-IgnoreReboot"),
+        UpdatesAutoReboot::Ask => String::new(),
+        UpdatesAutoReboot::At(at) => {
+            // Schedule the reboot for tomorrow when the time has already passed today
+            let days = if at <= now { 1 } else { 0 };
+            format!(
+                "-ScheduleReboot (Get-Date -Hour {} -Minute {} -Second 0).AddDays({days})",
+                at.hour(),
+                at.minute()
+            )
+        }
+    }
+}
+
+/// The PSWindowsUpdate command installing the updates, run as administrator by `Powershell::windows_update`.
+///
+/// The listed updates were already confirmed, so they are all accepted.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn install_windows_update_command(reboot: &str, filters: &str) -> String {
+    let mut cmd = vec!["Install-WindowsUpdate -MicrosoftUpdate -AcceptAll"];
+
+    if !reboot.is_empty() {
+        cmd.push(reboot);
+    }
+
+    if !filters.is_empty() {
+        cmd.push(filters);
+    }
+
+    cmd.push("-Verbose");
+    cmd.join(" ")
+}
+
+/// An update listed by `Get-WindowsUpdate`.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(not(windows), allow(dead_code))]
+struct WindowsUpdate {
+    kb: String,
+    size: String,
+    title: String,
+}
+
+impl Display for WindowsUpdate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}, {})", self.title, self.kb, self.size)
+    }
+}
+
+/// Parses the `KB<TAB>Size<TAB>Title` lines printed for each update by `Powershell::windows_update`.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_windows_updates(output: &str) -> Vec<WindowsUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim_end().splitn(3, '\t');
+            let kb = fields.next()?;
+            let size = fields.next()?;
+            let title = fields.next()?;
+            Some(WindowsUpdate {
+                kb: kb.to_string(),
+                size: size.to_string(),
+                title: title.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Maps the return value of the MDM `UpdateScanMethod` to an error message.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_store_scan_result(output: &str) -> Result<(), String> {
+    let output = output.trim();
+    let Ok(code) = output.parse::<i64>() else {
+        return Err(format!(
+            "Unexpected result of the Microsoft Store update scan: {output:?}"
+        ));
+    };
+
+    // The value is printed as a signed integer by some PowerShell versions
+    match code as u32 {
+        0 => Ok(()),
+        0x80070005 | 0x80041003 => Err(String::from(
+            "Access denied while scanning for Microsoft Store updates, run Topgrade as administrator",
+        )),
+        0x80041010 => Err(String::from(
+            "The MDM bridge is not available on this Windows edition, Microsoft Store apps can't be updated this way",
+        )),
+        code => Err(format!("The Microsoft Store update scan failed with {code:#010x}")),
+    }
+}
+
+/// Returns the module names, one per line, that don't match any of the `exclusions` glob patterns.
+fn included_modules(modules: &str, exclusions: &[String]) -> Vec<String> {
+    let exclusions: Vec<WildMatch> = exclusions.iter().map(|pattern| WildMatch::new(pattern)).collect();
+
+    modules
+        .lines()
+        .map(str::trim)
+        .filter(|module| !module.is_empty() && !exclusions.iter().any(|pattern| pattern.matches(module)))
+        .map(String::from)
+        .collect()
+}
+
+/// The command updating the installed modules, or only `module`, with PSResourceGet or the older PowerShellGet.
+fn update_modules_command(use_psresourceget: bool, module: Option<&str>, verbose: bool, force: bool) -> String {
+    let mut cmd = vec![if use_psresourceget {
+        "Update-PSResource"
+    } else {
+        "Update-Module"
+    }];
+
+    if let Some(module) = module {
+        cmd.push("-Name");
+        cmd.push(module);
+    }
+
+    if verbose {
+        cmd.push("-Verbose")
+    }
+
+    if force {
+        cmd.push("-Force")
+    }
+
+    cmd.join(" ")
+}
+
+/// The pwsh 7 script updating `modules` with `ForEach-Object -Parallel`, `throttle_limit` at a time.
+///
+/// The modules failing to update are collected, so that the script fails once all of them have been tried.
+fn parallel_update_modules_script(
+    modules: &[String],
+    throttle_limit: u32,
+    use_psresourceget: bool,
+    verbose: bool,
+    force: bool,
+) -> String {
+    let modules = modules
+        .iter()
+        .map(|module| format!("'{}'", module.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let update = update_modules_command(use_psresourceget, Some("$module"), verbose, force);
+
+    format!(
+        "$failed = @({modules}) | ForEach-Object -ThrottleLimit {throttle_limit} -Parallel {{ \
+         $module = $_; \
+         try {{ {update} -ErrorAction Stop }} \
+         catch {{ Write-Warning ('Failed to update ' + $module + ': ' + $_); $module }} }}; \
+         if ($failed) {{ Write-Error ('Failed to update the modules ' + ($failed -join ', ')); exit 1 }}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_modules_command() {
+        assert_eq!(update_modules_command(false, None, false, false), "Update-Module");
+        assert_eq!(
+            update_modules_command(false, None, true, true),
+            "Update-Module -Verbose -Force"
+        );
+        assert_eq!(update_modules_command(true, None, false, false), "Update-PSResource");
+        assert_eq!(
+            update_modules_command(true, None, true, true),
+            "Update-PSResource -Verbose -Force"
+        );
+        assert_eq!(
+            update_modules_command(false, Some("Pester"), false, true),
+            "Update-Module -Name Pester -Force"
+        );
+    }
+
+    #[test]
+    fn test_parallel_update_modules_script() {
+        let modules = [String::from("Pester"), String::from("It's.Module")];
+        assert_eq!(
+            parallel_update_modules_script(&modules, 4, false, false, true),
+            "$failed = @('Pester', 'It''s.Module') | ForEach-Object -ThrottleLimit 4 -Parallel { \
+             $module = $_; \
+             try { Update-Module -Name $module -Force -ErrorAction Stop } \
+             catch { Write-Warning ('Failed to update ' + $module + ': ' + $_); $module } }; \
+             if ($failed) { Write-Error ('Failed to update the modules ' + ($failed -join ', ')); exit 1 }"
+        );
+        assert!(parallel_update_modules_script(&modules, 8, true, true, false)
+            .contains("-ThrottleLimit 8 -Parallel { $module = $_; try { Update-PSResource -Name $module -Verbose -ErrorAction Stop }"));
+    }
+
+    #[test]
+    fn test_powershell_args() {
+        assert_eq!(
+            powershell_args(false, "Split-Path $profile"),
+            ["-NoProfile", "-Command", "Split-Path $profile"]
+        );
+        assert_eq!(
+            powershell_args(true, "Split-Path $profile"),
+            [
                 "-NoProfile",
+                "-ExecutionPolicy",
+                "Bypass",
                 "-Command",
-                &format!(
-                    "Start-Process powershell -Verb runAs -ArgumentList 'Import-Module PSWindowsUpdate; Install-WindowsUpdate -MicrosoftUpdate {} -Verbose'",
-                    if ctx.config().accept_all_windows_updates() {
-                        "-AcceptAll"
-                    } else {
-                        ""
-                    }
-                ),
-            ])
-            .status_checked()
+                "Split-Path $profile"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_install_windows_update_command() {
+        assert_eq!(
+            install_windows_update_command("", ""),
+            "Install-WindowsUpdate -MicrosoftUpdate -AcceptAll -Verbose"
+        );
+        assert_eq!(
+            install_windows_update_command("-IgnoreReboot", ""),
+            "Install-WindowsUpdate -MicrosoftUpdate -AcceptAll -IgnoreReboot -Verbose"
+        );
+
+        let filters = windows_update_filters(true, &[String::from("KB5034441"), String::from("KB5001716")]);
+        assert_eq!(
+            install_windows_update_command("", &filters),
+            "Install-WindowsUpdate -MicrosoftUpdate -AcceptAll -NotCategory \"Drivers\" \
+             -NotKBArticleID KB5034441,KB5001716 -Verbose"
+        );
+        assert_eq!(windows_update_filters(false, &[]), "");
+    }
+
+    #[test]
+    fn test_windows_update_reboot_argument() {
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(
+            windows_update_reboot_argument(UpdatesAutoReboot::Yes, noon),
+            "-AutoReboot"
+        );
+        assert_eq!(
+            windows_update_reboot_argument(UpdatesAutoReboot::No, noon),
+            "-IgnoreReboot"
+        );
+        assert_eq!(windows_update_reboot_argument(UpdatesAutoReboot::Ask, noon), "");
+
+        let night: UpdatesAutoReboot = "at=03:30".parse().unwrap();
+        assert_eq!(
+            windows_update_reboot_argument(night, noon),
+            "-ScheduleReboot (Get-Date -Hour 3 -Minute 30 -Second 0).AddDays(1)"
+        );
+        let evening: UpdatesAutoReboot = "at=18:05".parse().unwrap();
+        assert_eq!(
+            windows_update_reboot_argument(evening, noon),
+            "-ScheduleReboot (Get-Date -Hour 18 -Minute 5 -Second 0).AddDays(0)"
+        );
+
+        assert!("at=25:00".parse::<UpdatesAutoReboot>().is_err());
+        assert!("at=3".parse::<UpdatesAutoReboot>().is_err());
+        assert!("03:30".parse::<UpdatesAutoReboot>().is_err());
+    }
+
+    #[test]
+    fn test_parse_windows_updates() {
+        let output = "KB5034441\t250MB\tWindows Recovery Environment Update for Windows 10 Version 22H2\r\n\
+                      KB890830\t78MB\tWindows Malicious Software Removal Tool x64 - v5.121 (KB890830)\r\n";
+        assert_eq!(
+            parse_windows_updates(output),
+            [
+                WindowsUpdate {
+                    kb: String::from("KB5034441"),
+                    size: String::from("250MB"),
+                    title: String::from("Windows Recovery Environment Update for Windows 10 Version 22H2"),
+                },
+                WindowsUpdate {
+                    kb: String::from("KB890830"),
+                    size: String::from("78MB"),
+                    title: String::from("Windows Malicious Software Removal Tool x64 - v5.121 (KB890830)"),
+                },
+            ]
+        );
+        assert!(parse_windows_updates("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_store_scan_result() {
+        assert_eq!(parse_store_scan_result("0\r\n"), Ok(()));
+        assert!(parse_store_scan_result("2147942405\r\n")
+            .unwrap_err()
+            .contains("administrator"));
+        assert!(parse_store_scan_result("-2147024891\r\n")
+            .unwrap_err()
+            .contains("administrator"));
+        assert_eq!(
+            parse_store_scan_result("2147500037"),
+            Err(String::from("The Microsoft Store update scan failed with 0x80004005"))
+        );
+        assert!(parse_store_scan_result("").is_err());
+    }
+
+    #[test]
+    fn test_included_modules() {
+        let modules = "Az.Accounts\r\nAz.Compute\r\nPester\r\nPSReadLine\r\nVMware.PowerCLI\r\n";
+        let exclusions = [String::from("Az*"), String::from("VMware.*")];
+        assert_eq!(included_modules(modules, &exclusions), ["Pester", "PSReadLine"]);
+        assert_eq!(included_modules(modules, &[]).len(), 5);
     }
 }
@@ -1,54 +1,648 @@
-use color_eyre::eyre::Result;
-
-use crate::{
-    command::CommandExt, error::SkipStep, execution_context::ExecutionContext, terminal::print_separator, utils,
-};
-
-fn prepare_async_ssh_command(args: &mut Vec<&str>) {
-    args.insert(0, "ssh");
-    args.push("--keep");
-}
-
-pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
-    let ssh = utils::require("ssh")?;
-
-    let topgrade = ctx.config().remote_topgrade_path();
-    let mut args = vec!["-t", hostname];
-
-    if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-        args.extend(ssh_arguments.split_whitespace());
-    }
-
-    let env = format!("TOPGRADE_PREFIX={hostname}");
-    args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
-
-    if ctx.config().run_in_tmux() && !ctx.run_type().dry() {
-        #[cfg(unix)]
-        {
-            prepare_async_ssh_command(&mut args);
-            crate::tmux::run_command(ctx, hostname, &shell_words::join(args))?;
-            Err(SkipStep(String::from("Remote Topgrade launched in Tmux")).into())
-        }
-
-        #[cfg(not(unix))]
-        unreachable!("Tmux execution is only implemented in Unix");
-    } else if ctx.config().open_remotes_in_new_terminal() && !ctx.run_type().dry() && cfg!(windows) {
-        prepare_async_ssh_command(&mut args);
-        ctx.run_type().execute("wt").args(&args).spawn()?;
-        Err(SkipStep(String::from("Remote Topgrade launched in an external terminal")).into())
-    } else {
-        let mut args = vec!["-t", hostname];
-
-        if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-            args.extend(ssh_arguments.split_whitespace());
-        }
-
-        let env = format!("TOPGRADE_PREFIX={hostname}");
-        args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
-
-        print_separator(format!("Remote ({hostname})"));
-        println!("Connecting to {hostname}...");
-
-        ctx.run_type().execute(ssh).args(&args).status_checked()
-    }
-}
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::time::Duration;
+
+use clap::crate_version;
+use color_eyre::eyre::{self, Result};
+use console::style;
+use futures::future::{join, select, Either};
+use futures::stream::{iter, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command as AsyncCommand;
+use tokio::runtime;
+use tracing::debug;
+
+use crate::{
+    command::CommandExt,
+    config::{RemoteTopgrade, Step},
+    ctrlc,
+    error::{SkipStep, TopgradeError},
+    execution_context::ExecutionContext,
+    report::{self, SummaryNote},
+    terminal::print_separator,
+    utils,
+};
+
+/// Where Topgrade is installed on the remote hosts that don't have it, relative to the home directory.
+const REMOTE_INSTALL_PATH: &str = ".local/bin/topgrade";
+
+/// Printed by the remote shell after Topgrade exits, followed by the step results it wrote.
+const REPORT_SENTINEL: &str = "::topgrade-report::";
+
+/// How the step results of the remote Topgrade get back, to show them in the summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemoteReport {
+    None,
+    /// Left in `remote_report_path` and read by `fetch_remote_report` afterwards
+    File,
+    /// Printed after `REPORT_SENTINEL` at the end of the session, when its output is read
+    Printed,
+}
+
+/// The command that runs Topgrade on the remote host, passed to its login shell.
+///
+/// `--yes` is passed on when it applies to the remotes, as nobody could answer the prompts of
+/// concurrent runs.
+fn remote_topgrade_command(ctx: &ExecutionContext, remote: &RemoteTopgrade, topgrade: &str, keep: bool) -> String {
+    let mut command = String::from(topgrade);
+    if ctx.config().yes(Step::Remotes) {
+        command.push_str(" --yes");
+    }
+    if keep {
+        command.push_str(" --keep");
+    }
+    for arg in &remote.args {
+        command.push(' ');
+        command.push_str(&shell_words::quote(arg));
+    }
+    command
+}
+
+/// The options passed to SSH for `remote`: `ssh_arguments`, then the host's `ssh_args`.
+fn ssh_options(ctx: &ExecutionContext, remote: &RemoteTopgrade) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
+        options.extend(ssh_arguments.split_whitespace().map(String::from));
+    }
+    options.extend(remote.ssh_args.iter().cloned());
+    options
+}
+
+/// Where the remote Topgrade writes its step results.
+fn remote_report_path(remote: &RemoteTopgrade) -> String {
+    let host: String = remote
+        .host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("/tmp/topgrade-report-{}-{host}.json", process::id())
+}
+
+/// The command run by the remote shell to start `topgrade` on `remote`.
+///
+/// Unless `report` is `RemoteReport::None`, the remote Topgrade writes its step results to
+/// `remote_report_path`. They are requested through `TOPGRADE_REPORT_JSON` rather than
+/// `--report-json`, which older versions would reject, while they ignore the variable.
+fn remote_shell_command(
+    ctx: &ExecutionContext,
+    remote: &RemoteTopgrade,
+    topgrade: &str,
+    keep: bool,
+    report: RemoteReport,
+) -> String {
+    let path = remote_report_path(remote);
+    let mut words = vec![String::from("env"), format!("TOPGRADE_PREFIX={}", remote.host)];
+    if report != RemoteReport::None {
+        words.push(format!("TOPGRADE_REPORT_JSON={path}"));
+    }
+    words.extend(remote.env.iter().map(|(key, value)| format!("{key}={value}")));
+    let mut command = shell_words::join(words);
+    // `$SHELL` is expanded by the remote shell, so it isn't quoted
+    command.push_str(" $SHELL -lc ");
+    command.push_str(&shell_words::quote(&remote_topgrade_command(
+        ctx, remote, topgrade, keep,
+    )));
+
+    if report != RemoteReport::Printed {
+        return command;
+    }
+    // The login shell of the remote user may not be POSIX, so the exit status is kept by `sh`
+    let script =
+        format!("{command}; status=$?; echo {REPORT_SENTINEL}; cat {path} 2>/dev/null; rm -f {path}; exit $status");
+    format!("sh -c {}", shell_words::quote(&script))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransportKind {
+    Ssh,
+    Mosh,
+    Et,
+}
+
+/// The program that connects to the remote hosts, set with `ssh_command`.
+///
+/// Programs other than `mosh` and `et` are called like `ssh`, which lets wrappers around it work.
+#[derive(Debug, PartialEq, Eq)]
+struct Transport {
+    kind: TransportKind,
+    program: String,
+    args: Vec<String>,
+}
+
+impl Transport {
+    fn from_config(ctx: &ExecutionContext) -> Result<Self> {
+        Self::parse(ctx.config().ssh_command().unwrap_or("ssh"))
+    }
+
+    fn parse(command: &str) -> Result<Self> {
+        let mut words = shell_words::split(command)?.into_iter();
+        let program = words.next().ok_or_else(|| eyre::eyre!("`ssh_command` is empty"))?;
+        let kind = match Path::new(&program).file_stem().and_then(OsStr::to_str) {
+            Some("mosh") => TransportKind::Mosh,
+            Some("et") => TransportKind::Et,
+            _ => TransportKind::Ssh,
+        };
+
+        Ok(Transport {
+            kind,
+            program,
+            args: words.collect(),
+        })
+    }
+
+    /// The arguments after the program to run `remote_command` on `host`.
+    ///
+    /// `mosh` runs the command without a shell, so it is passed to `sh -c`, and gets the SSH
+    /// options through `--ssh`. `et` has no way to pass them on.
+    fn arguments(&self, host: &str, ssh_options: &[String], remote_command: String, tty: bool) -> Vec<String> {
+        let mut args = self.args.clone();
+        match self.kind {
+            TransportKind::Ssh => {
+                if tty {
+                    args.push(String::from("-t"));
+                }
+                args.push(host.to_string());
+                args.extend(ssh_options.iter().cloned());
+                args.push(remote_command);
+            }
+            TransportKind::Mosh => {
+                if !ssh_options.is_empty() {
+                    let mut ssh = vec![String::from("ssh")];
+                    ssh.extend(ssh_options.iter().cloned());
+                    args.push(format!("--ssh={}", shell_words::join(ssh)));
+                }
+                args.extend([
+                    host.to_string(),
+                    String::from("--"),
+                    String::from("sh"),
+                    String::from("-c"),
+                ]);
+                args.push(remote_command);
+            }
+            TransportKind::Et => {
+                args.push(String::from("-c"));
+                args.push(format!("{remote_command}; exit"));
+                args.push(host.to_string());
+            }
+        }
+        args
+    }
+
+    /// An SSH command to `remote`, for the commands whose output is read before Topgrade runs.
+    /// `mosh` and `et` only run interactive sessions, so plain `ssh` is used instead of them.
+    fn ssh_command(&self, ctx: &ExecutionContext, remote: &RemoteTopgrade) -> Command {
+        let mut command = match self.kind {
+            TransportKind::Ssh => {
+                let mut command = Command::new(&self.program);
+                command.args(&self.args);
+                command
+            }
+            TransportKind::Mosh | TransportKind::Et => Command::new("ssh"),
+        };
+        command.arg(&remote.host).args(ssh_options(ctx, remote));
+        command
+    }
+
+    /// The arguments after the program to run `topgrade` on `remote`.
+    fn topgrade_arguments(
+        &self,
+        ctx: &ExecutionContext,
+        remote: &RemoteTopgrade,
+        topgrade: &str,
+        tty: bool,
+        keep: bool,
+        report: RemoteReport,
+    ) -> Vec<String> {
+        self.arguments(
+            &remote.host,
+            &ssh_options(ctx, remote),
+            remote_shell_command(ctx, remote, topgrade, keep, report),
+            tty,
+        )
+    }
+}
+
+/// The Topgrade release that runs on a host, from the output of `uname -s` and `uname -m`.
+fn release_target(os: &str, machine: &str) -> Option<&'static str> {
+    match (os, machine) {
+        ("Linux", "x86_64") => Some("x86_64-unknown-linux-musl"),
+        ("Linux", "aarch64" | "arm64") => Some("aarch64-unknown-linux-musl"),
+        ("Linux", "armv7l") => Some("armv7-unknown-linux-gnueabihf"),
+        ("FreeBSD", "amd64") => Some("x86_64-unknown-freebsd"),
+        ("Darwin", "x86_64") => Some("x86_64-apple-darwin"),
+        ("Darwin", "arm64") => Some("aarch64-apple-darwin"),
+        _ => None,
+    }
+}
+
+/// The release matching this executable, when it's statically linked and can be copied as is.
+fn local_release_target() -> Option<&'static str> {
+    if cfg!(all(target_os = "linux", target_env = "musl")) {
+        release_target("Linux", env::consts::ARCH)
+    } else {
+        None
+    }
+}
+
+/// Returns the path of Topgrade on `remote`.
+///
+/// With `remote_install_missing`, Topgrade is installed to `REMOTE_INSTALL_PATH` when the remote
+/// doesn't have it: this executable is copied when it's a static build for the same architecture,
+/// otherwise the release of this version for the remote is downloaded there.
+fn remote_topgrade_path(ctx: &ExecutionContext, transport: &Transport, remote: &RemoteTopgrade) -> Result<String> {
+    let topgrade = ctx.config().remote_topgrade_path();
+    if !ctx.config().remote_install_missing() || ctx.run_type().dry() {
+        return Ok(topgrade.to_string());
+    }
+
+    let hostname = &remote.host;
+
+    let lookup = format!("command -v {topgrade} || command -v {REMOTE_INSTALL_PATH}");
+    let output = transport
+        .ssh_command(ctx, remote)
+        .args(["$SHELL", "-lc", &shell_words::quote(&lookup)])
+        // SSH exits with 255 when it can't connect
+        .output_checked_with_utf8(|output| {
+            if output.status.code() == Some(255) {
+                Err(())
+            } else {
+                Ok(())
+            }
+        })?;
+    // The path printed by `command -v` is the one that matched, which may not be on the `PATH`
+    if let Some(path) = output
+        .stdout
+        .lines()
+        .last()
+        .map(str::trim)
+        .filter(|path| output.status.success() && !path.is_empty())
+    {
+        return Ok(path.to_string());
+    }
+
+    let output = transport
+        .ssh_command(ctx, remote)
+        .args(["uname", "-s", "-m"])
+        .output_checked_utf8()?;
+    let uname = output.stdout.trim();
+    let (os, machine) = uname.split_once(' ').unwrap_or((uname, ""));
+    let Some(target) = release_target(os, machine) else {
+        return Err(SkipStep(format!(
+            "Topgrade isn't installed on {hostname}, and there is no Topgrade release for {os} on {machine}"
+        ))
+        .into());
+    };
+
+    let directory = REMOTE_INSTALL_PATH
+        .rsplit_once('/')
+        .map_or(".", |(directory, _)| directory);
+    if local_release_target() == Some(target) {
+        println!("Copying Topgrade to {hostname}...");
+        // The executable goes through SSH rather than scp, so that `ssh_arguments` apply as they are
+        transport
+            .ssh_command(ctx, remote)
+            .arg(format!(
+                "mkdir -p {directory} && cat > {REMOTE_INSTALL_PATH} && chmod +x {REMOTE_INSTALL_PATH}"
+            ))
+            .stdin(File::open(env::current_exe()?)?)
+            .status_checked()?;
+    } else {
+        let version = crate_version!();
+        let url = format!(
+            "https://github.com/topgrade-rs/topgrade/releases/download/v{version}/topgrade-v{version}-{target}.tar.gz"
+        );
+        println!("Downloading Topgrade {version} for {target} to {hostname}...");
+        transport
+            .ssh_command(ctx, remote)
+            .arg(format!(
+                "mkdir -p {directory} && curl -fsSL {url} | tar -xzf - -C {directory} topgrade"
+            ))
+            .status_checked()?;
+    }
+
+    Ok(REMOTE_INSTALL_PATH.to_string())
+}
+
+pub fn ssh_step(ctx: &ExecutionContext, remote: &RemoteTopgrade) -> Result<()> {
+    let transport = Transport::from_config(ctx)?;
+    let program = utils::require(&transport.program)?;
+    let hostname = &remote.host;
+    let topgrade = remote_topgrade_path(ctx, &transport, remote)?;
+
+    if ctx.config().run_in_tmux() && !ctx.run_type().dry() {
+        #[cfg(unix)]
+        {
+            let mut args = transport.topgrade_arguments(ctx, remote, &topgrade, true, true, RemoteReport::None);
+            args.insert(0, transport.program.clone());
+            crate::tmux::run_command(ctx, hostname, &shell_words::join(args))?;
+            Err(SkipStep(String::from("Remote Topgrade launched in Tmux")).into())
+        }
+
+        #[cfg(not(unix))]
+        unreachable!("Tmux execution is only implemented in Unix");
+    } else if ctx.config().open_remotes_in_new_terminal() && !ctx.run_type().dry() && cfg!(windows) {
+        let mut args = transport.topgrade_arguments(ctx, remote, &topgrade, true, true, RemoteReport::None);
+        args.insert(0, transport.program.clone());
+        ctx.run_type().execute("wt").args(&args).spawn()?;
+        Err(SkipStep(String::from("Remote Topgrade launched in an external terminal")).into())
+    } else {
+        print_separator(format!("Remote ({hostname})"));
+        println!("Connecting to {hostname}...");
+
+        // The output goes to the terminal, so the step results are read by another SSH session.
+        // `mosh` and `et` can't run one with the same options, so they don't get them.
+        let report = if transport.kind == TransportKind::Ssh && !ctx.run_type().dry() {
+            RemoteReport::File
+        } else {
+            RemoteReport::None
+        };
+        let result = ctx
+            .run_type()
+            .execute(program)
+            .args(transport.topgrade_arguments(ctx, remote, &topgrade, true, false, report))
+            .status_checked();
+        if report == RemoteReport::File {
+            fetch_remote_report(ctx, &transport, remote);
+        }
+        result
+    }
+}
+
+/// Read the step results written by the remote Topgrade, to show them in the summary.
+///
+/// This never asks for a password again, the results are only read when SSH can log in without
+/// one. Nothing is shown for the remote Topgrade versions that don't write them.
+fn fetch_remote_report(ctx: &ExecutionContext, transport: &Transport, remote: &RemoteTopgrade) {
+    let path = remote_report_path(remote);
+    let output = transport
+        .ssh_command(ctx, remote)
+        .args(["-o", "BatchMode=yes"])
+        .arg(format!("cat {path} && rm -f {path}"))
+        .output_checked_utf8();
+
+    match output {
+        Ok(output) => record_remote_report(ctx, &remote.host, &output.stdout),
+        Err(e) => debug!("No report from {}: {e:?}", remote.host),
+    }
+}
+
+/// Keep the step results of `host`, read from the JSON written by its Topgrade, for the summary.
+///
+/// A retry replaces the results of the previous attempt.
+fn record_remote_report(ctx: &ExecutionContext, host: &str, json: &str) {
+    match report::parse_json(json) {
+        Ok(steps) => ctx.set_summary_note(
+            &format!("remote-{host}"),
+            SummaryNote::RemoteSteps {
+                host: host.to_string(),
+                steps,
+            },
+        ),
+        Err(e) => debug!("No report from {host}: {e:?}"),
+    }
+}
+
+/// Runs Topgrade on the remote hosts concurrently, at most `remote_topgrade_parallel` at a time.
+///
+/// Returns an empty map when the remotes should run one after the other, through `ssh_step`, which
+/// is also the case without `--yes` for the remotes, as nobody could answer their prompts, and with
+/// `mosh` and `et` as they need a terminal.
+/// The output of each host is prefixed with its name. It is printed as it arrives, unless the
+/// output is a terminal, in which case it is printed once the host is done so that the hosts
+/// don't interleave.
+pub fn ssh_parallel(ctx: &ExecutionContext, remotes: &[&RemoteTopgrade]) -> HashMap<String, Result<()>> {
+    let limit = match ctx.config().remote_topgrade_parallel() {
+        Some(limit) if limit > 1 && remotes.len() > 1 => limit,
+        _ => return HashMap::new(),
+    };
+    if !ctx.config().should_run(Step::Remotes)
+        || !ctx.config().yes(Step::Remotes)
+        || ctx.run_type().dry()
+        || ctx.config().run_in_tmux()
+        || (ctx.config().open_remotes_in_new_terminal() && cfg!(windows))
+    {
+        return HashMap::new();
+    }
+    let transport = match Transport::from_config(ctx) {
+        Ok(transport) if transport.kind == TransportKind::Ssh => transport,
+        _ => return HashMap::new(),
+    };
+    let Some(program) = utils::which(&transport.program) else {
+        return HashMap::new();
+    };
+
+    print_separator("Remotes");
+
+    let runtime = match runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let error = e.to_string();
+            return remotes
+                .iter()
+                .map(|remote| (remote.host.clone(), Err(eyre::eyre!("{error}"))))
+                .collect();
+        }
+    };
+
+    // Topgrade is installed on the remotes missing it beforehand, one at a time
+    let prepared: Vec<_> = remotes
+        .iter()
+        .map(|remote| (remote, remote_topgrade_path(ctx, &transport, remote)))
+        .collect();
+
+    let stream = !std::io::stdout().is_terminal();
+    let futures_iterator = prepared.into_iter().map(|(remote, topgrade)| {
+        let program = &program;
+        let hostname = &remote.host;
+        let args = topgrade
+            .map(|topgrade| transport.topgrade_arguments(ctx, remote, &topgrade, false, false, RemoteReport::Printed));
+        async move {
+            let result = match args {
+                Ok(_) if ctrlc::interrupted() => Err(eyre::eyre!("Interrupted before connecting to {hostname}")),
+                Ok(args) => run_remote(ctx, program, hostname, &args, stream).await,
+                Err(e) => Err(e),
+            };
+            (hostname.to_string(), result)
+        }
+    });
+
+    runtime.block_on(async { iter(futures_iterator).buffer_unordered(limit).collect().await })
+}
+
+async fn run_remote(
+    ctx: &ExecutionContext,
+    program: &Path,
+    hostname: &str,
+    args: &[String],
+    stream: bool,
+) -> Result<()> {
+    let prefix = style(format!("[{hostname}]")).bold().to_string();
+
+    let mut child = AsyncCommand::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    // Both outputs go to the same buffer, to keep the order of their lines
+    let collected = RefCell::new(Vec::new());
+    let output = join(
+        read_lines(stdout, &prefix, stream, &collected),
+        read_lines(stderr, &prefix, stream, &collected),
+    );
+    match select(Box::pin(output), Box::pin(wait_for_interruption())).await {
+        Either::Left(((report, _), _)) => {
+            for line in collected.into_inner() {
+                println!("{prefix} {line}");
+            }
+            if let Some(report) = report {
+                record_remote_report(ctx, hostname, &report);
+            }
+        }
+        Either::Right(_) => {
+            child.kill().await?;
+            return Err(eyre::eyre!("Interrupted while running Topgrade on {hostname}"));
+        }
+    }
+
+    let status = child.wait().await?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(TopgradeError::ProcessFailed(format!("ssh {hostname}"), status).into())
+    }
+}
+
+/// Reads the lines of `reader`, printing them with `prefix` as they arrive when `stream` is set,
+/// or adding them to `collected` otherwise.
+///
+/// The lines after `REPORT_SENTINEL` are the step results of the remote Topgrade, they are returned
+/// and never printed.
+async fn read_lines(
+    reader: impl AsyncRead + Unpin,
+    prefix: &str,
+    stream: bool,
+    collected: &RefCell<Vec<String>>,
+) -> Option<String> {
+    let mut lines = BufReader::new(reader).lines();
+    let mut report: Option<String> = None;
+    while let Ok(Some(mut line)) = lines.next_line().await {
+        if let Some(report) = report.as_mut() {
+            report.push_str(&line);
+            report.push('\n');
+            continue;
+        }
+        // The last line of Topgrade may not end with a newline
+        if let Some(rest) = line.strip_suffix(REPORT_SENTINEL) {
+            line = rest.to_string();
+            report = Some(String::new());
+            if line.is_empty() {
+                continue;
+            }
+        }
+        if stream {
+            println!("{prefix} {line}");
+        } else {
+            collected.borrow_mut().push(line);
+        }
+    }
+    report
+}
+
+async fn wait_for_interruption() {
+    while !ctrlc::interrupted() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_target() {
+        assert_eq!(release_target("Linux", "x86_64"), Some("x86_64-unknown-linux-musl"));
+        assert_eq!(release_target("Linux", "aarch64"), Some("aarch64-unknown-linux-musl"));
+        assert_eq!(release_target("Darwin", "arm64"), Some("aarch64-apple-darwin"));
+        assert_eq!(release_target("FreeBSD", "amd64"), Some("x86_64-unknown-freebsd"));
+        assert_eq!(release_target("Linux", "riscv64"), None);
+        assert_eq!(release_target("OpenBSD", "amd64"), None);
+    }
+
+    #[test]
+    fn test_parse_transport() {
+        assert_eq!(
+            Transport::parse("ssh").unwrap(),
+            Transport {
+                kind: TransportKind::Ssh,
+                program: String::from("ssh"),
+                args: Vec::new(),
+            }
+        );
+        assert_eq!(
+            Transport::parse("ssh -J bastion.example.com -o 'ProxyCommand=nc -X 5 %h %p'").unwrap(),
+            Transport {
+                kind: TransportKind::Ssh,
+                program: String::from("ssh"),
+                args: vec![
+                    String::from("-J"),
+                    String::from("bastion.example.com"),
+                    String::from("-o"),
+                    String::from("ProxyCommand=nc -X 5 %h %p"),
+                ],
+            }
+        );
+        assert_eq!(
+            Transport::parse("mosh --predict=always").unwrap().kind,
+            TransportKind::Mosh
+        );
+        assert_eq!(Transport::parse("/usr/local/bin/et").unwrap().kind, TransportKind::Et);
+        assert_eq!(
+            Transport::parse("\"/opt/my wrapper/ssh-wrap\" --fast").unwrap(),
+            Transport {
+                kind: TransportKind::Ssh,
+                program: String::from("/opt/my wrapper/ssh-wrap"),
+                args: vec![String::from("--fast")],
+            }
+        );
+        assert!(Transport::parse("ssh -o 'unterminated").is_err());
+        assert!(Transport::parse("  ").is_err());
+    }
+
+    #[test]
+    fn test_transport_arguments() {
+        let options = [String::from("-p"), String::from("2222")];
+
+        let ssh = Transport::parse("ssh -J bastion").unwrap();
+        assert_eq!(
+            ssh.arguments("server1", &options, String::from("topgrade"), true),
+            ["-J", "bastion", "-t", "server1", "-p", "2222", "topgrade"]
+        );
+        assert_eq!(
+            ssh.arguments("server1", &[], String::from("topgrade"), false),
+            ["-J", "bastion", "server1", "topgrade"]
+        );
+
+        let mosh = Transport::parse("mosh").unwrap();
+        assert_eq!(
+            mosh.arguments("server1", &options, String::from("topgrade --yes"), true),
+            ["--ssh=ssh -p 2222", "server1", "--", "sh", "-c", "topgrade --yes"]
+        );
+
+        let et = Transport::parse("et").unwrap();
+        assert_eq!(
+            et.arguments("server1", &options, String::from("topgrade"), true),
+            ["-c", "topgrade; exit", "server1"]
+        );
+    }
+}
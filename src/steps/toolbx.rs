@@ -30,9 +30,13 @@ fn list_toolboxes(toolbx: &Path) -> Result<Vec<String>> {
 
 pub fn run_toolbx(ctx: &ExecutionContext) -> Result<()> {
     let toolbx = require("toolbox")?;
+    require("podman")?;
 
     print_separator("Toolbx");
-    let toolboxes = list_toolboxes(&toolbx)?;
+    let mut toolboxes = list_toolboxes(&toolbx)?;
+    if let Some(containers) = ctx.config().toolbx_containers() {
+        toolboxes.retain(|tb| containers.contains(tb));
+    }
     debug!("Toolboxes to inspect: {:?}", toolboxes);
 
     let mut topgrade_path = PathBuf::from("/run/host");
@@ -2,12 +2,16 @@ use std::ffi::OsStr;
 use std::path::Path;
 use std::path::PathBuf;
 
+use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use serde::Deserialize;
 use strum::AsRefStr;
 
 use crate::command::CommandExt;
+use crate::config::Config;
+#[cfg(windows)]
+use crate::config::WindowsSudo;
 use crate::execution_context::ExecutionContext;
 use crate::executor::Executor;
 use crate::terminal::print_separator;
@@ -22,7 +26,31 @@ pub struct Sudo {
 }
 
 impl Sudo {
+    /// Get the `sudo` binary chosen in the configuration, or detect it.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        #[cfg(windows)]
+        if let Some(windows_sudo) = config.windows_sudo() {
+            return match windows_sudo {
+                WindowsSudo::Gsudo => Self::new(SudoKind::Gsudo),
+                WindowsSudo::Sudo => Self::new(SudoKind::WindowsSudo),
+                WindowsSudo::None => None,
+            };
+        }
+
+        config.sudo_command().map_or_else(Self::detect, Self::new)
+    }
+
     /// Get the `sudo` binary for this platform.
+    #[cfg(windows)]
+    pub fn detect() -> Option<Self> {
+        which("gsudo")
+            .map(|p| (p, SudoKind::Gsudo))
+            .or_else(|| which("sudo").map(|p| (p, SudoKind::WindowsSudo)))
+            .map(|(path, kind)| Self { path, kind })
+    }
+
+    /// Get the `sudo` binary for this platform.
+    #[cfg(not(windows))]
     pub fn detect() -> Option<Self> {
         which("doas")
             .map(|p| (p, SudoKind::Doas))
@@ -45,6 +73,11 @@ impl Sudo {
     ///
     /// See: https://github.com/topgrade-rs/topgrade/issues/205
     pub fn elevate(&self, ctx: &ExecutionContext) -> Result<()> {
+        if let SudoKind::WindowsSudo = self.kind {
+            // The `sudo` of Windows can't cache credentials, every command shows its own prompt.
+            return Ok(());
+        }
+
         print_separator("Sudo");
         let mut cmd = ctx.run_type().execute(self);
         match self.kind {
@@ -85,10 +118,34 @@ impl Sudo {
                 //   Warm the access token and exit.
                 cmd.arg("-w");
             }
+            SudoKind::WindowsSudo => return Err(eyre!("The sudo of Windows can't elevate ahead of time")),
         }
         cmd.status_checked().wrap_err("Failed to elevate permissions")
     }
 
+    /// Execute a program with `sudo`, without going through a shell.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn execute<T: AsRef<OsStr>>(&self, ctx: &ExecutionContext, program: T) -> Executor {
+        let mut cmd = ctx.run_type().execute(self);
+
+        match self.kind {
+            // Run the program directly instead of through the detected shell.
+            // See: https://gerardog.github.io/gsudo/docs/usage
+            SudoKind::Gsudo => {
+                cmd.arg("-d");
+            }
+            // Run in the current console, so that the output is shown in the terminal
+            // whatever the configured mode of Windows' `sudo` is.
+            SudoKind::WindowsSudo => {
+                cmd.arg("--inline");
+            }
+            _ => {}
+        }
+
+        cmd.arg(program);
+        cmd
+    }
+
     /// Execute a command with `sudo`.
     pub fn execute_elevated(&self, ctx: &ExecutionContext, command: &Path, interactive: bool) -> Executor {
         let mut cmd = ctx.run_type().execute(self);
@@ -116,6 +173,11 @@ pub enum SudoKind {
     Gsudo,
     Pkexec,
     Please,
+    /// The `sudo` built into Windows 11, chosen with the `windows_sudo` option.
+    #[serde(skip)]
+    #[strum(serialize = "sudo")]
+    #[cfg_attr(not(windows), allow(dead_code))]
+    WindowsSudo,
 }
 
 impl AsRef<OsStr> for Sudo {
@@ -16,7 +16,7 @@ use tracing::{debug, error};
 use which_crate::which;
 
 use crate::command::CommandExt;
-use crate::report::StepResult;
+use crate::report::{StepResult, SummaryNote};
 
 lazy_static! {
     static ref TERMINAL: Mutex<Terminal> = Mutex::new(Terminal::new());
@@ -286,6 +286,19 @@ pub fn print_result<P: AsRef<str>>(key: P, result: &StepResult) {
     TERMINAL.lock().unwrap().print_result(key, result)
 }
 
+pub fn print_summary_note(note: &SummaryNote) {
+    match note {
+        SummaryNote::Info(text) => println!("\n{text}"),
+        SummaryNote::Warning(text) => print_warning(format!("\n{text}")),
+        SummaryNote::RemoteSteps { host, steps } => {
+            println!("\n{}", style(format!("remote: {host}")).bold());
+            for step in steps {
+                print_result(&step.key, &step.result);
+            }
+        }
+    }
+}
+
 /// Tells whether the terminal is dumb.
 pub fn is_dumb() -> bool {
     TERMINAL.lock().unwrap().width.is_none()